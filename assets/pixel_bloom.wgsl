@@ -0,0 +1,71 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+// quantized halos around bright texels, see pixel_bloom.rs
+struct PixelatedBloom {
+    threshold: f32,
+    radius: u32,
+    intensity: f32,
+    steps: u32,
+    dither: f32,
+}
+
+@group(0) @binding(0)
+var screen_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> settings: PixelatedBloom;
+
+const MAX_RADIUS: i32 = 16;
+
+fn luminance(color: vec3f) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+fn bayer_4x4(position: vec2u) -> f32 {
+    var bayer = array<f32, 16>(
+        0., 8., 2., 10.,
+        12., 4., 14., 6.,
+        3., 11., 1., 9.,
+        15., 7., 13., 5.
+    );
+    return bayer[(position.y % 4u) * 4u + position.x % 4u] / 16.;
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    let size = vec2<i32>(textureDimensions(screen_texture));
+    let color = textureLoad(screen_texture, texel, 0);
+    let radius = min(i32(settings.radius), MAX_RADIUS);
+
+    // the halo of the nearest glowing texel wins, so that
+    // halos keep their color instead of blurring together
+    var strength = 0.;
+    var glow = vec3(0.);
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let distance = length(vec2(f32(x), f32(y)));
+            if distance == 0. || distance > f32(radius) {
+                continue;
+            }
+            let neighbour = clamp(texel + vec2(x, y), vec2(0), size - 1);
+            let neighbour_color = textureLoad(screen_texture, neighbour, 0).rgb;
+            if luminance(neighbour_color) <= settings.threshold {
+                continue;
+            }
+            let falloff = 1. - distance / f32(radius + 1);
+            if falloff > strength {
+                strength = falloff;
+                glow = neighbour_color;
+            }
+        }
+    }
+
+    // rings of whole texels, dithered on the pixel grid
+    var level = strength;
+    if settings.steps > 0u && strength > 0. {
+        let steps = f32(settings.steps);
+        let dither_offset = (bayer_4x4(vec2u(texel)) - 0.5) * settings.dither;
+        level = saturate(ceil(strength * steps + dither_offset) / steps);
+    }
+    return vec4(color.rgb + glow * level * settings.intensity, color.a);
+}
@@ -0,0 +1,31 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+// upscales the image of a density camera into the pixelated
+// camera's color and depth, see pixel_density.rs
+@group(0) @binding(0)
+var density_color_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var density_depth_texture: texture_2d<f32>;
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) frag_depth: f32,
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> FragmentOutput {
+    // nearest neighbor, so that every density texel covers
+    // a block of low-res texels
+    let size = vec2<f32>(textureDimensions(density_color_texture));
+    let texel = vec2<i32>(min(in.uv * size, size - 1.));
+    let depth = textureLoad(density_depth_texture, texel, 0).x;
+    // nothing was rendered here (reverse z)
+    if depth <= 0. {
+        discard;
+    }
+
+    var out: FragmentOutput;
+    out.color = textureLoad(density_color_texture, texel, 0);
+    out.frag_depth = depth;
+    return out;
+}
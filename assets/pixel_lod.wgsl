@@ -0,0 +1,74 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+#import bevy_render::view::View
+
+// makes pixels bigger with the view distance, see
+// pixel_lod.rs
+struct PixelSizeByDistance {
+    start: f32,
+    end: f32,
+    max_size: u32,
+}
+
+@group(0) @binding(0)
+var screen_texture: texture_2d<f32>;
+#ifdef MULTISAMPLED
+@group(0) @binding(1)
+var depth_texture: texture_depth_multisampled_2d;
+#else
+@group(0) @binding(1)
+var depth_texture: texture_depth_2d;
+#endif
+@group(0) @binding(2)
+var<uniform> view: View;
+@group(0) @binding(3)
+var<uniform> settings: PixelSizeByDistance;
+
+fn load_depth(texel: vec2<i32>) -> f32 {
+#ifdef MULTISAMPLED
+    // nearest sample (reverse z), like the stored depth
+    var depth = 0.;
+    for (var i = 0u; i < textureNumSamples(depth_texture); i++) {
+        depth = max(depth, textureLoad(depth_texture, texel, i32(i)));
+    }
+    return depth;
+#else
+    return textureLoad(depth_texture, texel, 0);
+#endif
+}
+
+// the view space distance of a prepass depth
+fn view_distance(depth: f32) -> f32 {
+    let projection = view.projection;
+    if projection[3][3] == 1.0 {
+        return (projection[3][2] - depth) / projection[2][2];
+    }
+    // infinite reverse z perspective, [3][2] is the near plane
+    return projection[3][2] / max(depth, 1e-7);
+}
+
+fn pixel_size(texel: vec2<i32>) -> i32 {
+    let distance = view_distance(load_depth(texel));
+    let t = saturate(
+        (distance - settings.start)
+            / max(settings.end - settings.start, 1e-4)
+    );
+    let max_size = f32(max(settings.max_size, 1u));
+    return i32(round(mix(1., max_size, t)));
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+    let size = pixel_size(texel);
+    // every texel of a block takes the color of its corner.
+    // The blocks are aligned to the image, so that they
+    // don't swim when the camera moves sideways.
+    let corner = texel - texel % size;
+    // Only if the corner is just as far away, otherwise
+    // a near object's color would leak into the distance
+    // around its silhouette
+    if pixel_size(corner) == size {
+        return textureLoad(screen_texture, corner, 0);
+    }
+    return textureLoad(screen_texture, texel, 0);
+}
@@ -2,6 +2,9 @@
     pbr_fragment::pbr_input_from_standard_material,
     pbr_functions::alpha_discard,
     mesh_view_bindings::globals,
+    parallax_mapping::parallaxed_uv,
+    pbr_bindings,
+    pbr_types,
 }
 
 #ifdef PREPASS_PIPELINE
@@ -12,32 +15,88 @@
 #else
 #import bevy_pbr::{
     forward_io::{VertexOutput, FragmentOutput},
-    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
-    prepass_utils::{prepass_depth, prepass_normal}
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing, apply_fog},
+    prepass_utils::{prepass_depth, prepass_normal},
+    mesh_types,
+    mesh_view_types,
+    shadows::fetch_directional_shadow,
 }
+#import bevy_pbr::mesh_view_bindings as view_bindings
 #endif
 
 struct MyExtendedMaterial {
     quantize_steps: u32,
+    outline_strength: f32,
+    normal_edge_strength: f32,
+    depth_tolerance: f32,
+    dither: f32,
+    hue_shift: f32,
+    chroma_shift: f32,
+    shadow_hue: f32,
+    highlight_hue: f32,
+    debug_view: u32,
+    outline_width: u32,
+    outline_falloff: f32,
+    texture_pixelation: u32,
+    texel_density: f32,
+    texture_mip_bias: f32,
+    specular_threshold: f32,
+    specular_color: vec4<f32>,
+    rim_width: f32,
+    rim_color: vec4<f32>,
+    pixelated_fog: u32,
+    fog_steps: u32,
+    palette_swap_amount: f32,
+}
+
+// keep in sync with MAX_PALETTE_COLORS
+struct PixelPalette {
+    colors: array<vec4<f32>, 16>,
+    count: u32,
 }
 
 @group(1) @binding(100)
 var<uniform> my_extended_material: MyExtendedMaterial;
+@group(1) @binding(101)
+var<uniform> palette: PixelPalette;
+// the colors replacing the palette's, by index
+@group(1) @binding(102)
+var<uniform> palette_swap: PixelPalette;
+
+// debug views, see PixelDebugView
+const DEBUG_NONE: u32 = 0u;
+const DEBUG_DEPTH: u32 = 1u;
+const DEBUG_NORMALS: u32 = 2u;
+const DEBUG_OUTLINE: u32 = 3u;
+const DEBUG_NORMAL_EDGES: u32 = 4u;
+
+// texture pixelation modes, see TexturePixelation
+const TEXTURE_PIXELATION_OFF: u32 = 0u;
+const TEXTURE_PIXELATION_FILTERED: u32 = 1u;
+const TEXTURE_PIXELATION_QUANTIZED: u32 = 2u;
 
 @fragment
 fn fragment(
     in: VertexOutput,
     @builtin(front_facing) is_front: bool,
-    #ifdef MULTISAMPLED
-    @builtin(sample_index) sample_index: u32,
-#endif
 ) -> FragmentOutput {
-    #ifndef MULTISAMPLED
-        let sample_index = 0u;
-    #endif
     // generate a PbrInput struct from the StandardMaterial bindings
     var pbr_input = pbr_input_from_standard_material(in, is_front);
 
+#ifdef VERTEX_UVS
+    // outside of the branch, derivatives need uniform control flow
+    let uv_dx = dpdx(in.uv);
+    let uv_dy = dpdy(in.uv);
+    if my_extended_material.texture_pixelation != TEXTURE_PIXELATION_OFF
+        && (pbr_bindings::material.flags & pbr_types::STANDARD_MATERIAL_FLAGS_BASE_COLOR_TEXTURE_BIT) != 0u {
+        pbr_input.material.base_color = pbr_bindings::material.base_color
+            * pixelated_base_color(base_color_uv(in, pbr_input.V), uv_dx, uv_dy);
+#ifdef VERTEX_COLORS
+        pbr_input.material.base_color *= in.color;
+#endif
+    }
+#endif
+
     // we can optionally modify the input before lighting and alpha_discard is applied
     // pbr_input.material.base_color.b = pbr_input.material.base_color.r;
 
@@ -51,10 +110,34 @@ fn fragment(
     var out: FragmentOutput;
     // apply lighting
     out.color = apply_pbr_lighting(pbr_input);
-    
+
+    // fog before quantizing, so that it is banded like the
+    // lighting
+    let fog_enabled = (pbr_input.material.flags & pbr_types::STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT) != 0u;
+    if my_extended_material.pixelated_fog != 0u && fog_enabled
+        && view_bindings::fog.mode != mesh_view_types::FOG_MODE_OFF {
+        out.color = pixelated_fog(out.color, pbr_input.world_position.xyz);
+        // keeps main_pass_post_lighting_processing from fogging again
+        pbr_input.material.flags &= ~pbr_types::STANDARD_MATERIAL_FLAGS_FOG_ENABLED_BIT;
+    }
+
+    // hard highlights before quantizing, so that they land in
+    // a lightness band of their own
+    out.color = vec4(pixelated_highlights(pbr_input, out.color.rgb), out.color.a);
+
     let color_oklab = oklab_from_linear(out.color.xyz);
-    let quantized_lightness = floor(color_oklab.x * f32(my_extended_material.quantize_steps)) / f32(my_extended_material.quantize_steps);
-    out.color = vec4<f32>(linear_from_oklab(vec3<f32>(quantized_lightness, color_oklab.y, color_oklab.z)).xyz, out.color.a);
+    let steps = f32(my_extended_material.quantize_steps);
+    // ordered dithering moves pixels near a band edge into the
+    // neighbouring band
+    let dither_offset = (bayer_4x4(vec2u(in.position.xy)) - 0.5) * my_extended_material.dither;
+    let quantized_lightness = floor(color_oklab.x * steps + dither_offset) / steps;
+    let shifted_oklab = hue_shifted(
+        vec3<f32>(quantized_lightness, color_oklab.y, color_oklab.z),
+        pbr_input.material.base_color.rgb,
+        steps,
+    );
+    let quantized_oklab = swap_palette_color(snap_to_palette(shifted_oklab));
+    out.color = vec4<f32>(linear_from_oklab(quantized_oklab).xyz, out.color.a);
    
     // apply in-shader post processing (fog, alpha-premultiply, and also tonemapping, debanding if the camera is non-hdr)
     // note this does not include fullscreen postprocessing effects like bloom.
@@ -68,25 +151,21 @@ fn fragment(
         vec2i(
             i32(in.position.x),
             i32(in.position.y)
-        ),
-        sample_index
+        )
     );
 
-    let show_depth = 0u;
-    let show_normals = 0u;
-    let show_outline = 0u;
-    let show_normal_borders = 0u;
+    let debug_view = my_extended_material.debug_view;
 
-    if show_depth == 1u {
-        let depth = prepass_depth(in.position, sample_index);
+    if debug_view == DEBUG_DEPTH {
+        let depth = resolved_depth(in.position);
         out.color = vec4(depth, depth, depth, 1.0);
-    } else if show_normals == 1u {
-        let normal = prepass_normal(in.position, sample_index);
+    } else if debug_view == DEBUG_NORMALS {
+        let normal = resolved_normal(in.position);
         out.color = vec4(normal, 1.0);
-    } else if show_outline == 1u {
+    } else if debug_view == DEBUG_OUTLINE {
         out.color = vec4(f32(s), f32(s), f32(s), 1.);
-    } else if show_normal_borders == 1u {
-        let edge_mask = normal_edges(in.position, sample_index);
+    } else if debug_view == DEBUG_NORMAL_EDGES {
+        let edge_mask = normal_edges(in.position);
         out.color = vec4f(vec3(edge_mask), 1.0);
     } else {
         out.color = mix(
@@ -94,20 +173,158 @@ fn fragment(
             // out.color * 100.,
             // vec4(0.,0.,0.,1.),
             out.color / 4.,
-            s * 1.2
+            s * my_extended_material.outline_strength
         );
-        let edge_color = vec3(0.,0.,1.);
-        let edge_strength = 2.;
         out.color = mix(
             out.color,
             out.color * 4.,
-            normal_edges(in.position, sample_index)
+            normal_edges(in.position) * my_extended_material.normal_edge_strength
         );
     }
 
     return out;
 }
 
+#ifndef PREPASS_PIPELINE
+// The view's fog, with the amount of fog rounded to
+// `fog_steps` levels
+fn pixelated_fog(color: vec4<f32>, world_position: vec3<f32>) -> vec4<f32> {
+    let view_position = view_bindings::view.world_position.xyz;
+    // fog mixes the color into the fog color, so fogging black
+    // gives the fog's share and fogging white adds how much
+    // of the color is left
+    let fog_share = apply_fog(view_bindings::fog, vec4(0., 0., 0., 1.), world_position, view_position).rgb;
+    let transmittance = apply_fog(view_bindings::fog, vec4(1.), world_position, view_position).rgb - fog_share;
+    let steps = my_extended_material.fog_steps;
+    if steps == 0u {
+        return vec4(color.rgb * transmittance + fog_share, color.a);
+    }
+    let amount = 1. - transmittance;
+    let fog_color = fog_share / max(amount, vec3(1e-4));
+    let stepped = round(amount * f32(steps)) / f32(steps);
+    return vec4(mix(color.rgb, fog_color, stepped), color.a);
+}
+
+// Thresholded specular highlights and a fresnel rim light,
+// hard-edged so that they read as pixel art
+fn pixelated_highlights(pbr_input: pbr_types::PbrInput, color: vec3<f32>) -> vec3<f32> {
+    var highlighted = color;
+    let n = pbr_input.N;
+    let v = pbr_input.V;
+
+    if my_extended_material.specular_threshold > 0. {
+        let roughness = max(pbr_input.material.perceptual_roughness, 0.089);
+        let alpha = roughness * roughness;
+        // the blinn-phong exponent matching the roughness
+        let shininess = max(2. / (alpha * alpha) - 2., 1.);
+        if specular_peak(pbr_input, shininess) > my_extended_material.specular_threshold {
+            let specular = my_extended_material.specular_color;
+            highlighted = mix(highlighted, specular.rgb, specular.a);
+        }
+    }
+
+    if my_extended_material.rim_width > 0. {
+        // 1 at grazing angles
+        let fresnel = 1. - max(dot(n, v), 0.);
+        if fresnel > 1. - my_extended_material.rim_width {
+            let rim = my_extended_material.rim_color;
+            highlighted = mix(highlighted, rim.rgb, rim.a);
+        }
+    }
+    return highlighted;
+}
+
+// The strongest blinn-phong highlight of the directional
+// lights, in [0, 1]. Shadowed lights don't count.
+fn specular_peak(pbr_input: pbr_types::PbrInput, shininess: f32) -> f32 {
+    let n = pbr_input.N;
+    let v = pbr_input.V;
+    let inverse_view = view_bindings::view.inverse_view;
+    let view_z = dot(
+        vec4(inverse_view[0].z, inverse_view[1].z, inverse_view[2].z, inverse_view[3].z),
+        pbr_input.world_position,
+    );
+    var peak = 0.;
+    for (var i = 0u; i < view_bindings::lights.n_directional_lights; i++) {
+        let light = view_bindings::lights.directional_lights[i];
+        let l = light.direction_to_light;
+        if dot(n, l) <= 0. {
+            continue;
+        }
+        var shadow = 1.;
+        if (light.flags & mesh_view_types::DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u
+            && (pbr_input.flags & mesh_types::MESH_FLAGS_SHADOW_RECEIVER_BIT) != 0u {
+            shadow = fetch_directional_shadow(i, pbr_input.world_position, pbr_input.world_normal, view_z);
+        }
+        let h = normalize(l + v);
+        peak = max(peak, pow(max(dot(n, h), 0.), shininess) * shadow);
+    }
+    return peak;
+}
+#endif
+
+// The uv the base color texture is sampled at, with the same
+// parallax as pbr_input_from_standard_material, so that the
+// pixelated texture stays on the depth mapped surface
+#ifdef VERTEX_UVS
+fn base_color_uv(in: VertexOutput, V: vec3<f32>) -> vec2<f32> {
+    var uv = in.uv;
+#ifdef VERTEX_TANGENTS
+    if (pbr_bindings::material.flags & pbr_types::STANDARD_MATERIAL_FLAGS_DEPTH_MAP_BIT) != 0u {
+        let N = in.world_normal;
+        let T = in.world_tangent.xyz;
+        let B = in.world_tangent.w * cross(N, T);
+        // V in tangent space
+        let Vt = vec3(dot(V, T), dot(V, B), dot(V, N));
+        uv = parallaxed_uv(
+            pbr_bindings::material.parallax_depth_scale,
+            pbr_bindings::material.max_parallax_layer_count,
+            pbr_bindings::material.max_relief_mapping_search_steps,
+            uv,
+            // toward the surface
+            -Vt,
+        );
+    }
+#endif
+    return uv;
+}
+#endif
+
+// The base color texture, filtered down to the footprint of a
+// low-res pixel. uv_dx and uv_dy are the uv derivatives.
+fn pixelated_base_color(uv: vec2<f32>, uv_dx: vec2<f32>, uv_dy: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(pbr_bindings::base_color_texture));
+    // texels covered by one low-res pixel
+    let footprint = max(length(uv_dx * size), length(uv_dy * size));
+    // a power of two, so that cells nest like mips do
+    let cell = exp2(max(ceil(log2(footprint / max(my_extended_material.texel_density, 1e-4))), 0.));
+
+    var center = uv;
+    if my_extended_material.texture_pixelation == TEXTURE_PIXELATION_QUANTIZED {
+        // every pixel in a cell gets the color of its center
+        center = (floor(uv * size / cell) + 0.5) * cell / size;
+    }
+
+    // A grid of samples over the cell stands in for the mips
+    // that textures are usually loaded without. Textures with
+    // mips are sampled at the level matching the grid spacing.
+    let samples = u32(clamp(cell, 1., 4.));
+    let level = max(log2(cell / f32(samples)) + my_extended_material.texture_mip_bias, 0.);
+    var color = vec4(0.);
+    for (var y = 0u; y < samples; y++) {
+        for (var x = 0u; x < samples; x++) {
+            let offset = (vec2(f32(x), f32(y)) + 0.5) / f32(samples) - 0.5;
+            color += textureSampleLevel(
+                pbr_bindings::base_color_texture,
+                pbr_bindings::base_color_sampler,
+                center + offset * cell / size,
+                level,
+            );
+        }
+    }
+    return color / f32(samples * samples);
+}
+
 fn lightness_step(x: f32) -> f32 {
     if x < 10. {
         return 0.;
@@ -122,26 +339,51 @@ fn lightness_step(x: f32) -> f32 {
     }
 }
 
+fn is_orthographic() -> bool {
+    return view_bindings::view.projection[3][3] == 1.0;
+}
+
+// the view space distance of a prepass depth
+fn view_distance(depth: f32) -> f32 {
+    let projection = view_bindings::view.projection;
+    if is_orthographic() {
+        return (projection[3][2] - depth) / projection[2][2];
+    }
+    // infinite reverse z perspective, [3][2] is the near plane
+    return projection[3][2] / max(depth, 1e-7);
+}
+
 fn get_tolerance(d: f32, k: f32) -> f32
 {
-    let FAR = -10.;
-    let NEAR = 1.;
     // -------------------------------------------
     // Find a tolerance for depth that is constant
     // in view space (k in view space).
     //
-    // tol = k*ddx(ZtoDepth(z))
+    // tol = k * d(depth) / d(view distance)
     // -------------------------------------------
-    
-    let A: f32 = -   (FAR+NEAR)/(FAR - NEAR);
-    let B: f32 = -2.0 * FAR * NEAR /(FAR -NEAR);
-    
-    let new_d = d * 2.0 - 1.0;
-    
-    return -k*(new_d+A)*(new_d+A)/B;
+    let projection = view_bindings::view.projection;
+    if is_orthographic() {
+        // depth is linear in the view distance
+        return k * abs(projection[2][2]);
+    }
+    // depth = near / distance
+    return k * d * d / projection[3][2];
 }
 
-fn detect_silho(frag_coord: vec2i, dir: vec2i, sample_index: u32) -> f32
+// How many texels apart the depth samples of the outline are.
+// Perspective outlines are `outline_width` wide up to
+// `outline_falloff` and get thinner further away, down to a
+// single texel.
+fn outline_width(depth: f32) -> i32 {
+    let width = f32(max(my_extended_material.outline_width, 1u));
+    if is_orthographic() {
+        return i32(width);
+    }
+    let scaled = width * my_extended_material.outline_falloff / view_distance(depth);
+    return i32(clamp(round(scaled), 1., width));
+}
+
+fn detect_silho(frag_coord: vec2i, dir: vec2i) -> f32
 {
     // -------------------------------------------
     //   x0 ___ x1----o 
@@ -157,22 +399,19 @@ fn detect_silho(frag_coord: vec2i, dir: vec2i, sample_index: u32) -> f32
 
     let coord0 = (frag_coord + dir * -2);
     let pos0 = vec4f(f32(coord0.x), f32(coord0.y), 0., 1.); 
-    let x0: f32 = abs(prepass_depth(
-        pos0,
-        sample_index
-    ));
+    let x0: f32 = abs(resolved_depth(pos0));
 
     let coord1 = (frag_coord + dir * -1);
     let pos1 = vec4f(f32(coord1.x), f32(coord1.y), 0., 1.); 
-    let x1: f32 = abs(prepass_depth(pos1, sample_index));
+    let x1: f32 = abs(resolved_depth(pos1));
 
     let coord2 = (frag_coord + dir * 0);
     let pos2 = vec4f(f32(coord2.x), f32(coord2.y), 0., 1.); 
-    let x2: f32 = abs(prepass_depth(pos2, sample_index));
+    let x2: f32 = abs(resolved_depth(pos2));
 
     let coord3 = (frag_coord + dir * 1);
     let pos3 = vec4f(f32(coord3.x), f32(coord3.y), 0., 1.); 
-    let x3: f32 = abs(prepass_depth(pos3, sample_index));
+    let x3: f32 = abs(resolved_depth(pos3));
     
     let d0: f32 = (x1-x0);
     let d1: f32 = (x2-x3);
@@ -180,28 +419,30 @@ fn detect_silho(frag_coord: vec2i, dir: vec2i, sample_index: u32) -> f32
     let r0: f32 = x1 + d0 - x2;
     let r1: f32 = x2 + d1 - x1;
     
-    let tol: f32 = get_tolerance(x2, 0.04);
+    let tol: f32 = get_tolerance(x2, my_extended_material.depth_tolerance);
     
     return smoothstep(0.0, tol*tol, max( - r0*r1, 0.0));
     // return 0.;
 }
 
-fn detect_silho_coord(frag_coord: vec2i, sample_index: u32) -> f32
+fn detect_silho_coord(frag_coord: vec2i) -> f32
 {
+    let center = vec4f(f32(frag_coord.x), f32(frag_coord.y), 0., 1.);
+    let width = outline_width(resolved_depth(center));
     return max(
-        detect_silho(frag_coord, vec2i(1,0), sample_index), // Horizontal
-        detect_silho(frag_coord, vec2i(0,1), sample_index)  // Vertical
+        detect_silho(frag_coord, vec2i(width,0)), // Horizontal
+        detect_silho(frag_coord, vec2i(0,width))  // Vertical
     );
 }
 
-fn normal_edges(position: vec4f, sample_index: u32) -> f32 {
-    let normal = prepass_normal(position, sample_index);
+fn normal_edges(position: vec4f) -> f32 {
+    let normal = resolved_normal(position);
 
-    let neighbour_left = prepass_normal(position, sample_index);
-	let neighbour_right = prepass_normal( position + vec4(0.5, 0., 0., 0.), sample_index);
+    let neighbour_left = resolved_normal(position);
+	let neighbour_right = resolved_normal(position + vec4(0.5, 0., 0., 0.));
 	
-	let neighbour_top = prepass_normal( position, sample_index);
-	let neighbour_bottom = prepass_normal( position + vec4(0., 0.5, 0.,0.), sample_index);
+	let neighbour_top = resolved_normal(position);
+	let neighbour_bottom = resolved_normal(position + vec4(0., 0.5, 0.,0.));
 	
 	// ALBEDO = albedo.rgb * texture(texture_albedo, UV).rgb;
     // let ALBEDO = vec3(1.,1.,1.);
@@ -235,6 +476,123 @@ fn normal_edges(position: vec4f, sample_index: u32) -> f32 {
     );
 }
 
+// With msaa the prepasses hold several samples per texel.
+// The edge detection reads them resolved, so that it runs once
+// per texel and every sample of a texel gets the same outline.
+fn resolved_depth(position: vec4f) -> f32 {
+#ifdef MULTISAMPLED
+    // the nearest sample (reverse z)
+    var depth = 0.;
+    for (var i = 0u; i < textureNumSamples(view_bindings::depth_prepass_texture); i++) {
+        depth = max(depth, prepass_depth(position, i));
+    }
+    return depth;
+#else
+    return prepass_depth(position, 0u);
+#endif
+}
+
+fn resolved_normal(position: vec4f) -> vec3f {
+#ifdef MULTISAMPLED
+    // the average normal
+    var normal = vec3(0.);
+    let samples = textureNumSamples(view_bindings::normal_prepass_texture);
+    for (var i = 0u; i < samples; i++) {
+        normal += prepass_normal(position, i);
+    }
+    return normal / f32(samples);
+#else
+    return prepass_normal(position, 0u);
+#endif
+}
+
+// 4x4 ordered dithering threshold, in [0, 1)
+fn bayer_4x4(position: vec2u) -> f32 {
+    var bayer = array<f32, 16>(
+        0., 8., 2., 10.,
+        12., 4., 14., 6.,
+        3., 11., 1., 9.,
+        15., 7., 13., 5.
+    );
+    return bayer[(position.y % 4u) * 4u + position.x % 4u] / 16.;
+}
+
+// Turns the hue of a quantized oklab color toward
+// `shadow_hue` in bands darker than the base color and toward
+// `highlight_hue` in lighter ones, by `hue_shift` degrees per
+// band. Shadow bands also change chroma by `chroma_shift`
+// per band.
+fn hue_shifted(oklab: vec3f, base_color: vec3f, steps: f32) -> vec3f {
+    if my_extended_material.hue_shift == 0. && my_extended_material.chroma_shift == 0. {
+        return oklab;
+    }
+    let base_band = floor(oklab_from_linear(base_color).x * steps);
+    // whole bands, as the lightness is quantized
+    let bands = round(oklab.x * steps) - base_band;
+    var lch = oklch_from_oklab(oklab);
+    let shift = radians(my_extended_material.hue_shift) * abs(bands);
+    if bands < 0. {
+        lch.z = turn_toward(lch.z, radians(my_extended_material.shadow_hue), shift);
+        lch.y = max(lch.y * (1. - my_extended_material.chroma_shift * bands), 0.);
+    } else if bands > 0. {
+        lch.z = turn_toward(lch.z, radians(my_extended_material.highlight_hue), shift);
+    }
+    return oklab_from_oklch(lch);
+}
+
+// turns a hue toward another along the shorter way, by at most
+// max_angle radians
+fn turn_toward(hue: f32, goal: f32, max_angle: f32) -> f32 {
+    let delta = atan2(sin(goal - hue), cos(goal - hue));
+    return hue + clamp(delta, -max_angle, max_angle);
+}
+
+// lightness, chroma and hue in radians
+fn oklch_from_oklab(oklab: vec3f) -> vec3f {
+    return vec3(oklab.x, length(oklab.yz), atan2(oklab.z, oklab.y));
+}
+
+fn oklab_from_oklch(lch: vec3f) -> vec3f {
+    return vec3(lch.x, lch.y * cos(lch.z), lch.y * sin(lch.z));
+}
+
+// the index of the palette color closest to an oklab color
+fn palette_index(oklab: vec3f) -> u32 {
+    var closest = 0u;
+    var closest_distance = distance(oklab_from_linear(palette.colors[0].rgb), oklab);
+    for (var i = 1u; i < min(palette.count, 16u); i++) {
+        let candidate = distance(oklab_from_linear(palette.colors[i].rgb), oklab);
+        if candidate < closest_distance {
+            closest = i;
+            closest_distance = candidate;
+        }
+    }
+    return closest;
+}
+
+// the palette color closest to an oklab color, as oklab
+fn snap_to_palette(oklab: vec3f) -> vec3f {
+    if palette.count == 0u {
+        return oklab;
+    }
+    return oklab_from_linear(palette.colors[palette_index(oklab)].rgb);
+}
+
+// Moves a palette color toward the color replacing it, by the
+// already stepped `palette_swap_amount`. Colors without a
+// replacement are kept.
+fn swap_palette_color(oklab: vec3f) -> vec3f {
+    let amount = my_extended_material.palette_swap_amount;
+    if palette.count == 0u || palette_swap.count == 0u || amount <= 0. {
+        return oklab;
+    }
+    let index = palette_index(oklab);
+    if index >= palette_swap.count {
+        return oklab;
+    }
+    return mix(oklab, oklab_from_linear(palette_swap.colors[index].rgb), amount);
+}
+
 fn vec3_avg(color: vec3f) -> f32 {
 	return (color.r + color.g + color.b) / 3.0;
 }
@@ -0,0 +1,49 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+#ifdef STORE_DEPTH
+// stores the prepass depth of the pixelated camera
+// in a texture that can be sampled by other cameras
+#ifdef MULTISAMPLED
+@group(0) @binding(0)
+var prepass_depth_texture: texture_depth_multisampled_2d;
+#else
+@group(0) @binding(0)
+var prepass_depth_texture: texture_depth_2d;
+#endif
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(in.position.xy);
+#ifdef MULTISAMPLED
+    // resolve to the nearest sample (reverse z), so that
+    // anything covering part of a texel still occludes
+    var depth = 0.;
+    for (var i = 0u; i < textureNumSamples(prepass_depth_texture); i++) {
+        depth = max(depth, textureLoad(prepass_depth_texture, texel, i32(i)));
+    }
+#else
+    let depth = textureLoad(prepass_depth_texture, texel, 0);
+#endif
+    return vec4(depth, 0., 0., 1.);
+}
+#else
+// upscales the stored low-res depth into the depth
+// buffer of a hybrid camera
+@group(0) @binding(0)
+var low_res_depth_texture: texture_2d<f32>;
+
+struct FragmentOutput {
+    @builtin(frag_depth) frag_depth: f32,
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> FragmentOutput {
+    // nearest neighbor, the same way the color is upscaled
+    let size = vec2<f32>(textureDimensions(low_res_depth_texture));
+    let texel = vec2<i32>(min(in.uv * size, size - 1.));
+
+    var out: FragmentOutput;
+    out.frag_depth = textureLoad(low_res_depth_texture, texel, 0).x;
+    return out;
+}
+#endif
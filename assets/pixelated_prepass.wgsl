@@ -0,0 +1,94 @@
+// The prepass of pixelated materials. Like the StandardMaterial
+// prepass, but the normal prepass also follows depth maps, so
+// that normal_edges in pixelated.wgsl sees the same detailed
+// surface the main pass lights and quantizes.
+#import bevy_pbr::{
+    pbr_prepass_functions,
+    pbr_bindings::material,
+    pbr_types,
+    pbr_functions,
+    prepass_io,
+    mesh_view_bindings::view,
+    parallax_mapping::parallaxed_uv,
+}
+
+#ifdef PREPASS_FRAGMENT
+@fragment
+fn fragment(
+    in: prepass_io::VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> prepass_io::FragmentOutput {
+    pbr_prepass_functions::prepass_alpha_discard(in);
+
+    var out: prepass_io::FragmentOutput;
+
+#ifdef DEPTH_CLAMP_ORTHO
+    out.frag_depth = in.clip_position_unclamped.z;
+#endif
+
+#ifdef NORMAL_PREPASS
+    if (material.flags & pbr_types::STANDARD_MATERIAL_FLAGS_UNLIT_BIT) == 0u {
+        let double_sided = (material.flags & pbr_types::STANDARD_MATERIAL_FLAGS_DOUBLE_SIDED_BIT) != 0u;
+
+#ifdef VERTEX_UVS
+        var uv = in.uv;
+#ifdef VERTEX_TANGENTS
+        // the same parallax as pbr_input_from_standard_material
+        if (material.flags & pbr_types::STANDARD_MATERIAL_FLAGS_DEPTH_MAP_BIT) != 0u {
+            let is_orthographic = view.projection[3].w == 1.0;
+            let V = pbr_functions::calculate_view(in.world_position, is_orthographic);
+            let N = in.world_normal;
+            let T = in.world_tangent.xyz;
+            let B = in.world_tangent.w * cross(N, T);
+            // V in tangent space
+            let Vt = vec3(dot(V, T), dot(V, B), dot(V, N));
+            uv = parallaxed_uv(
+                material.parallax_depth_scale,
+                material.max_parallax_layer_count,
+                material.max_relief_mapping_search_steps,
+                uv,
+                // toward the surface
+                -Vt,
+            );
+        }
+#endif
+#endif
+
+        let world_normal = pbr_functions::prepare_world_normal(
+            in.world_normal,
+            double_sided,
+            is_front,
+        );
+        let normal = pbr_functions::apply_normal_mapping(
+            material.flags,
+            world_normal,
+            double_sided,
+            is_front,
+#ifdef VERTEX_TANGENTS
+#ifdef STANDARDMATERIAL_NORMAL_MAP
+            in.world_tangent,
+#endif
+#endif
+#ifdef VERTEX_UVS
+            uv,
+#endif
+            view.mip_bias,
+        );
+        out.normal = vec4(normal * 0.5 + vec3(0.5), 1.0);
+    } else {
+        out.normal = vec4(in.world_normal * 0.5 + vec3(0.5), 1.0);
+    }
+#endif
+
+#ifdef MOTION_VECTOR_PREPASS
+    out.motion_vector = pbr_prepass_functions::calculate_motion_vector(in.world_position, in.previous_world_position);
+#endif
+
+    return out;
+}
+#else
+@fragment
+fn fragment(in: prepass_io::VertexOutput) {
+    pbr_prepass_functions::prepass_alpha_discard(in);
+}
+#endif
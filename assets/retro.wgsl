@@ -0,0 +1,79 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+// old screen effects, see retro.rs. Every effect is off at 0,
+// and only the ones that are on are compiled in through their
+// shader defs.
+struct RetroPostProcess {
+    curvature: f32,
+    chromatic_aberration: f32,
+    scanlines: f32,
+    scanline_count: f32,
+    phosphor_mask: f32,
+    vignette: f32,
+}
+
+@group(0) @binding(0)
+var screen_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var screen_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> settings: RetroPostProcess;
+
+const TAU: f32 = 6.28318530718;
+
+// bulges the uv out from the center, like the glass of a crt
+fn curved(uv: vec2<f32>) -> vec2<f32> {
+#ifndef CURVATURE
+    return uv;
+#else
+    let centered = uv * 2. - 1.;
+    let bent = centered * (1. + settings.curvature * dot(centered, centered));
+    return bent * 0.5 + 0.5;
+#endif
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let uv = curved(in.uv);
+#ifdef CURVATURE
+    // outside of the curved glass
+    if any(uv < vec2(0.)) || any(uv > vec2(1.)) {
+        return vec4(0., 0., 0., 1.);
+    }
+#endif
+
+    let center = textureSampleLevel(screen_texture, screen_sampler, uv, 0.);
+    var color = center.rgb;
+
+#ifdef CHROMATIC_ABERRATION
+    // red and blue are pulled apart toward the edges
+    let size = vec2<f32>(textureDimensions(screen_texture));
+    let offset = (uv - 0.5) * 2. * settings.chromatic_aberration / size;
+    color.r = textureSampleLevel(screen_texture, screen_sampler, uv + offset, 0.).r;
+    color.b = textureSampleLevel(screen_texture, screen_sampler, uv - offset, 0.).b;
+#endif
+
+#ifdef SCANLINES
+    // darker between the scanlines
+    let scanline = 0.5 - 0.5 * cos(uv.y * settings.scanline_count * TAU);
+    color *= 1. - settings.scanlines * scanline;
+#endif
+
+#ifdef PHOSPHOR_MASK
+    // red, green and blue stripes of an aperture grille, one
+    // window pixel each
+    let stripe = u32(in.position.x) % 3u;
+    var mask = vec3(0.25);
+    mask[stripe] = 1.;
+    color *= mix(vec3(1.), mask * 1.5, settings.phosphor_mask);
+#endif
+
+#ifdef VIGNETTE
+    // darker toward the corners
+    let edges = uv * (1. - uv);
+    let vignette = pow(saturate(edges.x * edges.y * 16.), 0.25);
+    color *= mix(1., vignette, settings.vignette);
+#endif
+
+    return vec4(color, center.a);
+}
@@ -12,10 +12,8 @@ use gen_04_pixels::{
         PixelatedCamera, PixelatedPassLayer,
         PixelatingPlugin,
     },
-    rotators::{
-        circle_rotator_system, rotator_system, light_rotator_system,
-    },
-    screenshots::screenshot_on_spacebar,
+    rotators::RotatorPlugin,
+    screenshots::PixelatedScreenshotPlugin,
     PixelatedExtension,
 };
 use std::f32::consts::PI;
@@ -27,18 +25,10 @@ fn main() {
                 .set(ImagePlugin::default_nearest()),
             WorldInspectorPlugin::new(),
         ))
-        .add_plugins(PixelatingPlugin)
+        .add_plugins((PixelatingPlugin::default(), PixelatedScreenshotPlugin))
         .add_systems(PostStartup, setup)
-        .add_systems(
-            Update,
-            (
-                circle_rotator_system,
-                light_rotator_system,
-                rotator_system,
-                screenshot_on_spacebar,
-                rotate,
-            ),
-        )
+        .add_plugins(RotatorPlugin)
+        .add_systems(Update, rotate)
         .insert_resource(Msaa::Off)
         .run();
 }
@@ -52,6 +42,7 @@ fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
     // mut materials: ResMut<Assets<StandardMaterial>>,
     pixelated_pass_layer: Res<PixelatedPassLayer>,
     mut pixelated: ResMut<
@@ -81,6 +72,11 @@ fn setup(
         },
         extension: PixelatedExtension {
             quantize_steps: 5,
+            // edit the file while the example runs
+            style: Some(asset_server.load(
+                "styles/default.pixelstyle.ron",
+            )),
+            ..default()
         },
     });
     let shapes = [
@@ -143,6 +139,7 @@ fn setup(
             },
             extension: PixelatedExtension {
                 quantize_steps: 15,
+                ..default()
             },
         }),
         ..default()
@@ -175,7 +172,7 @@ fn setup(
         NormalPrepass,
         // PixelatedCamera causes this camera to be used to generate the
         // pixelated scene
-        PixelatedCamera,
+        PixelatedCamera::default(),
     ));
 }
 
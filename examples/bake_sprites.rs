@@ -0,0 +1,186 @@
+//! Bakes the taxi into a pixel-art sprite sheet, without a
+//! window:
+//!
+//! ```sh
+//! # 8 angles, 1 frame, written to sprites/taxi.png and .ron
+//! cargo run --example bake_sprites
+//! cargo run --example bake_sprites -- <angles> <frames> <output.png>
+//! ```
+use bevy::{
+    core_pipeline::{
+        clear_color::ClearColorConfig,
+        prepass::{DepthPrepass, NormalPrepass},
+    },
+    pbr::ExtendedMaterial,
+    prelude::*,
+    utils::HashMap,
+};
+use bevy_scene_hook::{
+    HookPlugin, HookedSceneBundle, SceneHook,
+};
+use gen_04_pixels::{
+    baker::{
+        BakeSubject, SpriteSheetBaker, SpriteSheetSettings,
+    },
+    headless::headless_plugins,
+    pixelating_plugin::{
+        PixelatedCamera, PixelatedPassLayer,
+        PixelatingPlugin,
+    },
+    PixelatedExtension,
+};
+use std::process::ExitCode;
+
+type PixelatedMaterial =
+    ExtendedMaterial<StandardMaterial, PixelatedExtension>;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let defaults = SpriteSheetSettings::default();
+    let settings = SpriteSheetSettings {
+        angles: args
+            .next()
+            .and_then(|angles| angles.parse().ok())
+            .unwrap_or(defaults.angles),
+        frames: args
+            .next()
+            .and_then(|frames| frames.parse().ok())
+            .unwrap_or(defaults.frames),
+        ..defaults
+    };
+    let output = args
+        .next()
+        .unwrap_or_else(|| "sprites/taxi.png".into());
+
+    let mut app = App::new();
+    app.add_plugins(headless_plugins())
+        .add_plugins((
+            PixelatingPlugin::headless(),
+            HookPlugin,
+        ))
+        .add_systems(PostStartup, setup)
+        .add_systems(Update, use_pixelated_materials);
+
+    let mut baker = SpriteSheetBaker::new(app, settings);
+    match baker.bake().and_then(|sheet| sheet.save(&output))
+    {
+        Ok(descriptor) => {
+            println!(
+                "sprite sheet written to {output} and {}",
+                descriptor.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    commands.spawn((
+        Camera3dBundle {
+            camera_3d: Camera3d {
+                // the sprites get a transparent background
+                clear_color: ClearColorConfig::Custom(
+                    Color::NONE,
+                ),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 3., 4.0)
+                .looking_at(
+                    Vec3::new(0., 0.3, 0.),
+                    Vec3::Y,
+                ),
+            projection: Projection::Orthographic(
+                OrthographicProjection {
+                    scale: 0.005,
+                    ..default()
+                },
+            ),
+            ..default()
+        },
+        // depth prepass is required for pixelated.wgsl
+        DepthPrepass,
+        // normal prepass is required for pixelated.wgsl
+        NormalPrepass,
+        PixelatedCamera::default(),
+    ));
+
+    let layer = pixelated_pass_layer.0;
+    commands.spawn((
+        HookedSceneBundle {
+            scene: SceneBundle {
+                scene: asset_server
+                    .load("car-kit/taxi.glb#Scene0"),
+                ..default()
+            },
+            // the meshes of the scene are rendered by the
+            // pixelated camera, with the materials swapped by
+            // use_pixelated_materials
+            hook: SceneHook::new(
+                move |entity, commands| {
+                    type Material =
+                        Handle<StandardMaterial>;
+                    if entity.contains::<Material>() {
+                        commands.insert(layer);
+                    }
+                },
+            ),
+        },
+        BakeSubject,
+    ));
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: 10000.,
+            ..default()
+        },
+        transform: Transform::from_xyz(4.0, 10.0, 4.0)
+            .looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
+
+// Swaps the standard materials of the scene for pixelated
+// ones with the same colors and textures. Meshes sharing a
+// material share its pixelated copy.
+fn use_pixelated_materials(
+    mut commands: Commands,
+    meshes: Query<
+        (Entity, &Handle<StandardMaterial>),
+        Added<Handle<StandardMaterial>>,
+    >,
+    standard: Res<Assets<StandardMaterial>>,
+    mut pixelated: ResMut<Assets<PixelatedMaterial>>,
+    mut swapped: Local<
+        HashMap<
+            AssetId<StandardMaterial>,
+            Handle<PixelatedMaterial>,
+        >,
+    >,
+) {
+    for (entity, material) in &meshes {
+        let Some(base) = standard.get(material) else {
+            continue;
+        };
+        let handle = swapped
+            .entry(material.id())
+            .or_insert_with(|| {
+                pixelated.add(ExtendedMaterial {
+                    base: base.clone(),
+                    extension: default(),
+                })
+            })
+            .clone();
+        commands
+            .entity(entity)
+            .remove::<Handle<StandardMaterial>>()
+            .insert(handle);
+    }
+}
@@ -0,0 +1,129 @@
+//! Renders a fixed scene without a window, once without msaa
+//! and once with 4x msaa, and compares it against the images
+//! in `golden/`. Exits with an error if the look changed or a
+//! golden image is missing, so it can run in CI:
+//!
+//! ```sh
+//! cargo run --example golden
+//! # after an intended change to the look
+//! PIXELATE_BLESS=1 cargo run --example golden
+//! ```
+use bevy::{
+    core_pipeline::prepass::{DepthPrepass, NormalPrepass},
+    pbr::ExtendedMaterial,
+    prelude::*,
+};
+use gen_04_pixels::{
+    colors,
+    golden::{check_golden, Tolerance},
+    headless::{headless_plugins, HeadlessRenderer},
+    pixelating_plugin::{
+        PixelatedCamera, PixelatedPassLayer,
+        PixelatingPlugin,
+    },
+    PixelatedExtension,
+};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut result = ExitCode::SUCCESS;
+    // the outlines must work with and without msaa
+    for (msaa, golden) in [
+        (Msaa::Off, "golden/cubes.png"),
+        (Msaa::Sample4, "golden/cubes-msaa4.png"),
+    ] {
+        if let Err(error) = render_and_check(msaa, golden) {
+            eprintln!("{golden}: {error}");
+            result = ExitCode::FAILURE;
+        }
+    }
+    result
+}
+
+fn render_and_check(
+    msaa: Msaa,
+    golden: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut app = App::new();
+    app.add_plugins(headless_plugins())
+        .add_plugins(PixelatingPlugin::headless())
+        .add_systems(PostStartup, setup)
+        .insert_resource(msaa);
+
+    let mut renderer = HeadlessRenderer::new(app);
+    // let pipelines compile before capturing
+    renderer.update(10);
+    let frame = renderer
+        .capture()
+        .ok_or("the low-res image was never read back")?;
+    check_golden(frame, golden, Tolerance::default())?;
+    Ok(())
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut pixelated: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                PixelatedExtension,
+            >,
+        >,
+    >,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_xyz(0.0, 6., 12.0)
+                .looking_at(Vec3::new(0., 1., 0.), Vec3::Y),
+            ..default()
+        },
+        // depth prepass is required for pixelated.wgsl
+        DepthPrepass,
+        // normal prepass is required for pixelated.wgsl
+        NormalPrepass,
+        PixelatedCamera::default(),
+    ));
+
+    for (x, color) in [
+        (-4., colors::MAUVE),
+        (0., colors::TEAL),
+        (4., colors::PEACH),
+    ] {
+        commands.spawn((
+            MaterialMeshBundle {
+                mesh: meshes.add(Mesh::from(shape::Cube {
+                    size: 2.0,
+                })),
+                transform: Transform::from_xyz(x, 1.0, 0.0)
+                    .with_rotation(Quat::from_rotation_y(
+                        0.6,
+                    )),
+                material: pixelated.add(ExtendedMaterial {
+                    base: StandardMaterial {
+                        base_color: color,
+                        perceptual_roughness: 1.0,
+                        ..default()
+                    },
+                    extension: PixelatedExtension {
+                        quantize_steps: 5,
+                        ..default()
+                    },
+                }),
+                ..default()
+            },
+            pixelated_pass_layer.0,
+        ));
+    }
+    commands.spawn(PointLightBundle {
+        point_light: PointLight {
+            intensity: 4500.0,
+            range: 100.,
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_xyz(8.0, 16.0, 8.0),
+        ..default()
+    });
+}
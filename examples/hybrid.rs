@@ -0,0 +1,139 @@
+use bevy::{
+    core_pipeline::prepass::{DepthPrepass, NormalPrepass},
+    pbr::ExtendedMaterial,
+    prelude::*,
+};
+use gen_04_pixels::{
+    colors,
+    hybrid::PixelatedHybridCamera,
+    pixelating_plugin::{
+        PixelatedCamera, PixelatedCompositing,
+        PixelatedPassLayer, PixelatingPlugin,
+    },
+    rotators::{rotator_system, Rotate},
+    PixelatedExtension,
+};
+
+fn main() {
+    App::new()
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest()),
+        )
+        .add_plugins(PixelatingPlugin::default())
+        // the low-res depth is required for hybrid cameras
+        .insert_resource(
+            PixelatedCompositing::ColorAndDepth,
+        )
+        .add_systems(
+            PostStartup,
+            (setup_cameras, setup_scene),
+        )
+        .add_systems(Update, (rotator_system, bob))
+        .insert_resource(Msaa::Off)
+        .run();
+}
+
+#[derive(Component)]
+struct Crystal;
+
+fn setup_cameras(mut commands: Commands) {
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_xyz(0.0, 6., 12.0)
+                .looking_at(Vec3::new(0., 1., 0.), Vec3::Y),
+            ..default()
+        },
+        // depth prepass is required for pixelated.wgsl
+        DepthPrepass,
+        // normal prepass is required for pixelated.wgsl
+        NormalPrepass,
+        PixelatedCamera::default(),
+    ));
+    // renders the crystal at full resolution, behind
+    // or in front of the pixelated cube
+    commands.spawn((
+        Camera3dBundle::default(),
+        PixelatedHybridCamera,
+    ));
+}
+
+fn setup_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut pixelated: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                PixelatedExtension,
+            >,
+        >,
+    >,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes
+                .add(Mesh::from(shape::Cube { size: 3.0 })),
+            transform: Transform::from_xyz(0.0, 1.5, 0.0),
+            material: pixelated.add(ExtendedMaterial {
+                base: StandardMaterial {
+                    base_color: colors::MAUVE,
+                    perceptual_roughness: 1.0,
+                    ..default()
+                },
+                extension: PixelatedExtension {
+                    quantize_steps: 5,
+                    ..default()
+                },
+            }),
+            ..default()
+        },
+        Rotate,
+        pixelated_pass_layer.0,
+    ));
+    // not on the pixelated pass layer, so only the
+    // hybrid camera renders it
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(
+                shape::Icosphere {
+                    radius: 0.6,
+                    subdivisions: 1,
+                }
+                .try_into()
+                .unwrap(),
+            ),
+            material: materials.add(StandardMaterial {
+                base_color: colors::TEAL,
+                emissive: colors::TEAL,
+                ..default()
+            }),
+            transform: Transform::from_xyz(0.0, 1.5, 0.0),
+            ..default()
+        },
+        Crystal,
+    ));
+    commands.spawn(PointLightBundle {
+        point_light: PointLight {
+            intensity: 4500.0,
+            range: 100.,
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_xyz(8.0, 16.0, 8.0),
+        ..default()
+    });
+}
+
+// moves the crystal through the cube
+fn bob(
+    time: Res<Time>,
+    mut crystals: Query<&mut Transform, With<Crystal>>,
+) {
+    for mut transform in &mut crystals {
+        transform.translation.z =
+            (time.elapsed_seconds() / 2.).sin() * 3.;
+    }
+}
@@ -19,10 +19,9 @@ use gen_04_pixels::{
         PixelatedCamera,
         PixelatedPassLayer, PixelatingPlugin,
     },
-    rotators::{
-        circle_rotator_system, rotator_system, Rotate, light_rotator_system, CircleRotate,
-    },
-    screenshots::screenshot_on_spacebar,
+    rotators::{RotatorPlugin, Rotate, CircleRotate},
+    recorder::PixelatedRecorderPlugin,
+    screenshots::PixelatedScreenshotPlugin,
     PixelatedExtension,
 };
 use std::f32::consts::{FRAC_PI_4, PI};
@@ -35,16 +34,12 @@ fn main() {
             WorldInspectorPlugin::new(),
             HookPlugin
         ))
-        .add_plugins(PixelatingPlugin)
-        .add_systems(
-            Update,
-            (
-                circle_rotator_system,
-                light_rotator_system,
-                rotator_system,
-                screenshot_on_spacebar,
-            ),
-        )
+        .add_plugins((
+            PixelatingPlugin::default(),
+            PixelatedScreenshotPlugin,
+            PixelatedRecorderPlugin,
+        ))
+        .add_plugins(RotatorPlugin)
         .insert_resource(Msaa::Off)
         .add_state::<MyStates>()
         .add_loading_state(
@@ -115,7 +110,7 @@ fn setup_camera(
         NormalPrepass,
         // PixelatedCamera causes this camera to be used to generate the
         // pixelated scene
-        PixelatedCamera,
+        PixelatedCamera::default(),
     ));
 }
 
@@ -159,6 +154,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 15,
+                    ..default()
                 },
             }),
             ..default()
@@ -179,6 +175,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 5,
+                    ..default()
                 },
             }),
             ..default()
@@ -0,0 +1,207 @@
+use bevy::{
+    core_pipeline::prepass::{DepthPrepass, NormalPrepass},
+    pbr::ExtendedMaterial,
+    prelude::*,
+    window::PrimaryWindow,
+};
+use gen_04_pixels::{
+    colors,
+    pixel_viewport::PixelViewport,
+    pixelating_plugin::{
+        PixelatedCamera, PixelatedOverlayCamera,
+        PixelatedPassLayer, PixelatingPlugin,
+    },
+    rotators::{rotator_system, Rotate},
+    PixelatedExtension,
+};
+
+fn main() {
+    App::new()
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest()),
+        )
+        .add_plugins(PixelatingPlugin::default())
+        .add_systems(
+            PostStartup,
+            (setup_cameras, setup_scene, setup_ui),
+        )
+        .add_systems(
+            Update,
+            (
+                rotator_system,
+                move_cursor,
+                show_cursor_texel,
+                click_to_move,
+            ),
+        )
+        .insert_resource(Msaa::Off)
+        .run();
+}
+
+#[derive(Component)]
+struct Cursor;
+
+#[derive(Component)]
+struct TexelText;
+
+fn setup_cameras(mut commands: Commands) {
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_xyz(0.0, 6., 12.0)
+                .looking_at(Vec3::new(0., 1., 0.), Vec3::Y),
+            ..default()
+        },
+        // depth prepass is required for pixelated.wgsl
+        DepthPrepass,
+        // normal prepass is required for pixelated.wgsl
+        NormalPrepass,
+        PixelatedCamera::default(),
+    ));
+    // renders UI and sprites at full resolution
+    // on top of the pixelated scene
+    commands.spawn((
+        Camera2dBundle::default(),
+        PixelatedOverlayCamera::default(),
+    ));
+}
+
+fn setup_scene(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut pixelated: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                PixelatedExtension,
+            >,
+        >,
+    >,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes
+                .add(Mesh::from(shape::Cube { size: 3.0 })),
+            transform: Transform::from_xyz(0.0, 2., 0.0),
+            material: pixelated.add(ExtendedMaterial {
+                base: StandardMaterial {
+                    base_color: colors::MAUVE,
+                    perceptual_roughness: 1.0,
+                    ..default()
+                },
+                extension: PixelatedExtension {
+                    quantize_steps: 5,
+                    ..default()
+                },
+            }),
+            ..default()
+        },
+        Rotate,
+        pixelated_pass_layer.0,
+    ));
+    commands.spawn(PointLightBundle {
+        point_light: PointLight {
+            intensity: 4500.0,
+            range: 100.,
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_xyz(8.0, 16.0, 8.0),
+        ..default()
+    });
+}
+
+fn setup_ui(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "crisp text over pixelated 3d",
+            TextStyle {
+                font_size: 32.,
+                color: colors::TEXT,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.),
+            left: Val::Px(12.),
+            ..default()
+        }),
+        TexelText,
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: colors::YELLOW,
+                custom_size: Some(Vec2::splat(12.)),
+                ..default()
+            },
+            ..default()
+        },
+        Cursor,
+    ));
+}
+
+fn move_cursor(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<
+        (&Camera, &GlobalTransform),
+        With<PixelatedOverlayCamera>,
+    >,
+    mut cursors: Query<&mut Transform, With<Cursor>>,
+) {
+    let (camera, camera_transform) = cameras.single();
+    let Some(position) = windows
+        .single()
+        .cursor_position()
+        .and_then(|cursor| {
+            camera.viewport_to_world_2d(
+                camera_transform,
+                cursor,
+            )
+        })
+    else {
+        return;
+    };
+    for mut transform in &mut cursors {
+        transform.translation = position.extend(1.);
+    }
+}
+
+fn show_cursor_texel(
+    pixel_viewport: PixelViewport,
+    mut texts: Query<&mut Text, With<TexelText>>,
+) {
+    let value = match pixel_viewport.cursor_texel() {
+        Some(texel) => {
+            format!("texel: {}, {}", texel.x, texel.y)
+        }
+        None => "texel: -".to_string(),
+    };
+    for mut text in &mut texts {
+        text.sections[0].value = value.clone();
+    }
+}
+
+// moves the cube to the point on the ground under the cursor
+fn click_to_move(
+    pixel_viewport: PixelViewport,
+    buttons: Res<Input<MouseButton>>,
+    mut cubes: Query<&mut Transform, With<Rotate>>,
+) {
+    if !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(ray) = pixel_viewport.cursor_ray() else {
+        return;
+    };
+    let Some(distance) =
+        ray.intersect_plane(Vec3::new(0., 2., 0.), Vec3::Y)
+    else {
+        return;
+    };
+    for mut transform in &mut cubes {
+        transform.translation = ray.get_point(distance);
+    }
+}
@@ -0,0 +1,164 @@
+//! A row of cubes running into the distance, seen by a
+//! perspective pixelated camera. The outlines are two texels
+//! wide up close and thin out in the distance, where the
+//! pixels get bigger, and fade into the sky in steps of fog.
+use bevy::{
+    core_pipeline::{
+        clear_color::ClearColorConfig,
+        prepass::{DepthPrepass, NormalPrepass},
+    },
+    pbr::ExtendedMaterial,
+    prelude::*,
+};
+use gen_04_pixels::{
+    colors,
+    fog::PixelatedFog,
+    pixel_lod::PixelSizeByDistance,
+    pixelating_plugin::{
+        PixelatedCamera, PixelatedPassLayer,
+        PixelatingPlugin,
+    },
+    rotators::{Rotate, RotatorPlugin},
+    PixelatedExtension,
+};
+
+fn main() {
+    App::new()
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest()),
+        )
+        .add_plugins((
+            PixelatingPlugin::default(),
+            RotatorPlugin,
+        ))
+        .add_systems(PostStartup, setup)
+        .insert_resource(Msaa::Off)
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut pixelated: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                PixelatedExtension,
+            >,
+        >,
+    >,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    commands.spawn((
+        Camera3dBundle {
+            camera_3d: Camera3d {
+                clear_color: ClearColorConfig::Custom(
+                    colors::SKY,
+                ),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 4., 10.0)
+                .looking_at(
+                    Vec3::new(0., 1., -20.),
+                    Vec3::Y,
+                ),
+            projection: Projection::Perspective(
+                PerspectiveProjection {
+                    fov: 0.8,
+                    ..default()
+                },
+            ),
+            ..default()
+        },
+        // depth prepass is required for pixelated.wgsl
+        DepthPrepass,
+        // normal prepass is required for pixelated.wgsl
+        NormalPrepass,
+        PixelatedCamera::default(),
+        PixelSizeByDistance {
+            start: 25.,
+            end: 90.,
+            max_size: 3,
+        },
+        FogSettings {
+            color: colors::SKY,
+            falloff: FogFalloff::Linear {
+                start: 20.,
+                end: 80.,
+            },
+            ..default()
+        },
+        // fades into the fog in 4 steps, before quantizing
+        PixelatedFog { steps: 4 },
+    ));
+
+    let cube =
+        meshes.add(Mesh::from(shape::Cube { size: 2.0 }));
+    let cube_colors =
+        [colors::MAUVE, colors::TEAL, colors::PEACH];
+    for i in 0..30 {
+        let z = -6. * i as f32;
+        for x in [-4., 4.] {
+            commands.spawn((
+                MaterialMeshBundle {
+                    mesh: cube.clone(),
+                    transform: Transform::from_xyz(
+                        x, 1.0, z,
+                    ),
+                    material: pixelated.add(
+                        ExtendedMaterial {
+                            base: StandardMaterial {
+                                base_color: cube_colors
+                                    [i % cube_colors.len()],
+                                perceptual_roughness: 1.0,
+                                ..default()
+                            },
+                            extension: PixelatedExtension {
+                                // outlines close to the camera
+                                // are two texels wide
+                                outline_width: 2,
+                                outline_falloff: 15.,
+                                ..default()
+                            },
+                        },
+                    ),
+                    ..default()
+                },
+                Rotate,
+                pixelated_pass_layer.0,
+            ));
+        }
+    }
+
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Mesh::from(shape::Plane {
+                size: 400.,
+                subdivisions: 1,
+            })),
+            transform: Transform::from_xyz(0., 0., -80.),
+            material: pixelated.add(ExtendedMaterial {
+                base: StandardMaterial {
+                    base_color: colors::BASE,
+                    perceptual_roughness: 1.0,
+                    ..default()
+                },
+                extension: PixelatedExtension::default(),
+            }),
+            ..default()
+        },
+        pixelated_pass_layer.0,
+    ));
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: 10000.,
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_xyz(4.0, 10.0, 4.0)
+            .looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
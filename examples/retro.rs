@@ -0,0 +1,169 @@
+//! Pixelated cubes on an old screen. The number keys toggle
+//! the effects: 1 curvature, 2 chromatic aberration, 3
+//! scanlines, 4 phosphor mask and 5 vignette.
+use bevy::{
+    core_pipeline::prepass::{DepthPrepass, NormalPrepass},
+    pbr::ExtendedMaterial,
+    prelude::*,
+};
+use gen_04_pixels::{
+    colors,
+    pixelating_plugin::{
+        PixelatedCamera, PixelatedOutputCamera,
+        PixelatedPassLayer, PixelatingPlugin,
+    },
+    retro::RetroPostProcess,
+    rotators::{Rotate, RotatorPlugin},
+    PixelatedExtension,
+};
+
+fn main() {
+    App::new()
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest()),
+        )
+        .add_plugins((
+            PixelatingPlugin::default(),
+            RotatorPlugin,
+        ))
+        .add_systems(PostStartup, setup)
+        .add_systems(Update, (add_crt, toggle_effects))
+        .insert_resource(Msaa::Off)
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut pixelated: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                PixelatedExtension,
+            >,
+        >,
+    >,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_xyz(0.0, 6., 12.0)
+                .looking_at(Vec3::new(0., 1., 0.), Vec3::Y),
+            projection: Projection::Orthographic(
+                OrthographicProjection {
+                    scale: 0.03,
+                    ..default()
+                },
+            ),
+            ..default()
+        },
+        // depth prepass is required for pixelated.wgsl
+        DepthPrepass,
+        // normal prepass is required for pixelated.wgsl
+        NormalPrepass,
+        PixelatedCamera::default(),
+    ));
+
+    for (x, z, color) in [
+        (-4., 0., colors::MAUVE),
+        (0., -1., colors::TEAL),
+        (4., 0., colors::PEACH),
+    ] {
+        commands.spawn((
+            MaterialMeshBundle {
+                mesh: meshes.add(Mesh::from(shape::Cube {
+                    size: 2.0,
+                })),
+                transform: Transform::from_xyz(x, 1.0, z),
+                material: pixelated.add(ExtendedMaterial {
+                    base: StandardMaterial {
+                        base_color: color,
+                        perceptual_roughness: 1.0,
+                        ..default()
+                    },
+                    extension: PixelatedExtension::default(
+                    ),
+                }),
+                ..default()
+            },
+            Rotate,
+            pixelated_pass_layer.0,
+        ));
+    }
+
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Mesh::from(shape::Plane {
+                size: 20.,
+                subdivisions: 1,
+            })),
+            material: pixelated.add(ExtendedMaterial {
+                base: StandardMaterial {
+                    base_color: colors::BASE,
+                    perceptual_roughness: 1.0,
+                    ..default()
+                },
+                extension: PixelatedExtension::default(),
+            }),
+            ..default()
+        },
+        pixelated_pass_layer.0,
+    ));
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: 10000.,
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_xyz(4.0, 10.0, 4.0)
+            .looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
+
+// the output camera is spawned by the plugin
+fn add_crt(
+    mut commands: Commands,
+    cameras: Query<Entity, Added<PixelatedOutputCamera>>,
+) {
+    for camera in &cameras {
+        commands
+            .entity(camera)
+            .insert(RetroPostProcess::default());
+    }
+}
+
+fn toggle_effects(
+    input: Res<Input<KeyCode>>,
+    mut effects: Query<&mut RetroPostProcess>,
+) {
+    let on = RetroPostProcess::default();
+    for mut effect in &mut effects {
+        let toggle = |value: &mut f32, on: f32| {
+            *value = if *value == 0. { on } else { 0. };
+        };
+        if input.just_pressed(KeyCode::Key1) {
+            toggle(&mut effect.curvature, on.curvature);
+        }
+        if input.just_pressed(KeyCode::Key2) {
+            toggle(
+                &mut effect.chromatic_aberration,
+                on.chromatic_aberration,
+            );
+        }
+        if input.just_pressed(KeyCode::Key3) {
+            toggle(&mut effect.scanlines, on.scanlines);
+        }
+        if input.just_pressed(KeyCode::Key4) {
+            toggle(
+                &mut effect.phosphor_mask,
+                on.phosphor_mask,
+            );
+        }
+        if input.just_pressed(KeyCode::Key5) {
+            toggle(&mut effect.vignette, on.vignette);
+        }
+    }
+}
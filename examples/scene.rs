@@ -13,14 +13,14 @@ use bevy::{
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use gen_04_pixels::{
     colors,
+    pixel_bloom::PixelatedBloom,
+    pixel_density::PixelDensity,
     pixelating_plugin::{
         PixelatedCamera,
         PixelatedPassLayer, PixelatingPlugin,
     },
-    rotators::{
-        circle_rotator_system, rotator_system, Rotate, light_rotator_system,
-    },
-    screenshots::screenshot_on_spacebar,
+    rotators::{RotatorPlugin, Rotate},
+    screenshots::PixelatedScreenshotPlugin,
     PixelatedExtension,
 };
 use std::f32::consts::{FRAC_PI_4, FRAC_PI_8, PI};
@@ -32,20 +32,12 @@ fn main() {
                 .set(ImagePlugin::default_nearest()),
             WorldInspectorPlugin::new(),
         ))
-        .add_plugins(PixelatingPlugin)
+        .add_plugins((PixelatingPlugin::default(), PixelatedScreenshotPlugin))
         .add_systems(
             PostStartup,
             (setup_camera, setup_scene),
         )
-        .add_systems(
-            Update,
-            (
-                circle_rotator_system,
-                light_rotator_system,
-                rotator_system,
-                screenshot_on_spacebar,
-            ),
-        )
+        .add_plugins(RotatorPlugin)
         .insert_resource(Msaa::Off)
         .run();
 }
@@ -92,7 +84,12 @@ fn setup_camera(
         NormalPrepass,
         // PixelatedCamera causes this camera to be used to generate the
         // pixelated scene
-        PixelatedCamera,
+        PixelatedCamera::default(),
+        // the emissive cylinder glows in rings of texels
+        PixelatedBloom {
+            threshold: 1.,
+            ..default()
+        },
     ));
 }
 
@@ -135,6 +132,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 5,
+                    ..default()
                 },
             }),
             ..default()
@@ -163,6 +161,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 15,
+                    ..default()
                 },
             }),
             ..default()
@@ -196,6 +195,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 15,
+                    ..default()
                 },
             }),
             ..default()
@@ -228,6 +228,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 15,
+                    ..default()
                 },
             }),
             ..default()
@@ -256,6 +257,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 15,
+                    ..default()
                 },
             }),
             ..default()
@@ -296,6 +298,7 @@ fn setup_scene(
                     },
                     extension: PixelatedExtension {
                         quantize_steps: 15,
+                        ..default()
                     },
                 }),
                 ..default()
@@ -327,6 +330,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 5,
+                    ..default()
                 },
             }),
             ..default()
@@ -364,6 +368,7 @@ fn setup_scene(
                 },
                 extension: PixelatedExtension {
                     quantize_steps: 15,
+                    ..default()
                 },
             }),
             // material: materials.add(StandardMaterial {
@@ -382,6 +387,8 @@ fn setup_scene(
             ..default()
         },
         // ShadowR
+        // the ground uses a chunkier pixel grid
+        PixelDensity(0.5),
         pixelated_pass_layer.0,
     ));
 
@@ -412,6 +419,7 @@ fn setup_scene(
             },
             extension: PixelatedExtension {
                 quantize_steps: 15,
+                ..default()
             },
         });
     commands.spawn((
@@ -0,0 +1,196 @@
+//! 2d sprites drawn into the low-res image between pixelated
+//! cubes. The sprites keep one texel per low-res pixel and are
+//! occluded by the cubes in front of them. The cubes turn at
+//! 12 fps, like the sprites would be animated.
+use bevy::{
+    core_pipeline::prepass::{DepthPrepass, NormalPrepass},
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::render_resource::{
+        Extent3d, TextureDimension, TextureFormat,
+    },
+};
+use gen_04_pixels::{
+    colors,
+    pixelating_plugin::{
+        PixelatedCamera, PixelatedPassLayer,
+        PixelatingPlugin,
+    },
+    rotators::{Rotate, RotatorPlugin},
+    sprites::{PixelatedSprite, SpriteOrientation},
+    stepped_animation::SteppedAnimation,
+    PixelatedExtension,
+};
+
+fn main() {
+    App::new()
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest()),
+        )
+        .add_plugins((
+            PixelatingPlugin::default(),
+            RotatorPlugin,
+        ))
+        .add_systems(PostStartup, setup)
+        .insert_resource(Msaa::Off)
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut pixelated: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                PixelatedExtension,
+            >,
+        >,
+    >,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    commands.spawn((
+        Camera3dBundle {
+            transform: Transform::from_xyz(0.0, 6., 12.0)
+                .looking_at(Vec3::new(0., 1., 0.), Vec3::Y),
+            projection: Projection::Orthographic(
+                OrthographicProjection {
+                    scale: 0.03,
+                    ..default()
+                },
+            ),
+            ..default()
+        },
+        // depth prepass is required for pixelated.wgsl
+        DepthPrepass,
+        // normal prepass is required for pixelated.wgsl
+        NormalPrepass,
+        PixelatedCamera::default(),
+    ));
+
+    for (x, z, color) in [
+        (-3., 0., colors::MAUVE),
+        (3., -2., colors::TEAL),
+        (0., 3., colors::PEACH),
+    ] {
+        commands.spawn((
+            MaterialMeshBundle {
+                mesh: meshes.add(Mesh::from(shape::Cube {
+                    size: 2.0,
+                })),
+                transform: Transform::from_xyz(x, 1.0, z),
+                material: pixelated.add(ExtendedMaterial {
+                    base: StandardMaterial {
+                        base_color: color,
+                        perceptual_roughness: 1.0,
+                        ..default()
+                    },
+                    extension: PixelatedExtension::default(
+                    ),
+                }),
+                ..default()
+            },
+            Rotate,
+            // turn at the frame rate of the sprites
+            SteppedAnimation { fps: 12. },
+            pixelated_pass_layer.0,
+        ));
+    }
+
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Mesh::from(shape::Plane {
+                size: 20.,
+                subdivisions: 1,
+            })),
+            material: pixelated.add(ExtendedMaterial {
+                base: StandardMaterial {
+                    base_color: colors::BASE,
+                    perceptual_roughness: 1.0,
+                    ..default()
+                },
+                extension: PixelatedExtension::default(),
+            }),
+            ..default()
+        },
+        pixelated_pass_layer.0,
+    ));
+
+    // characters standing between the cubes
+    let character = images.add(character_image());
+    for (x, z, orientation) in [
+        (0., 0., SpriteOrientation::Billboard),
+        (-4., 3., SpriteOrientation::Upright),
+        (4., 1., SpriteOrientation::Upright),
+    ] {
+        commands.spawn((
+            SpriteBundle {
+                texture: character.clone(),
+                sprite: Sprite {
+                    anchor:
+                        bevy::sprite::Anchor::BottomCenter,
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, 0., z),
+                ..default()
+            },
+            PixelatedSprite { orientation },
+        ));
+    }
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: 10000.,
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_xyz(4.0, 10.0, 4.0)
+            .looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
+
+/// A 12x20 figure on a transparent background
+fn character_image() -> Image {
+    const WIDTH: usize = 12;
+    const HEIGHT: usize = 20;
+    let skin = colors::ROSEWATER.as_rgba_u8();
+    let shirt = colors::RED.as_rgba_u8();
+    let legs = colors::BLUE.as_rgba_u8();
+
+    let mut data = vec![0; WIDTH * HEIGHT * 4];
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let dx = x as i32 * 2 - WIDTH as i32 + 1;
+            let texel = match y {
+                // head
+                0..=5 if dx.abs() <= 5 => Some(skin),
+                // body and arms
+                6..=12 if dx.abs() <= 9 => Some(shirt),
+                // legs, with a gap between them
+                13..=19 if (2..=7).contains(&dx.abs()) => {
+                    Some(legs)
+                }
+                _ => None,
+            };
+            if let Some(texel) = texel {
+                let offset = (y * WIDTH + x) * 4;
+                data[offset..offset + 4]
+                    .copy_from_slice(&texel);
+            }
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: WIDTH as u32,
+            height: HEIGHT as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
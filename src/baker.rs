@@ -0,0 +1,353 @@
+//! Bakes 3d models into pixel-art sprite sheets with the
+//! pixelated pipeline.
+//!
+//! Every entity with a [`BakeSubject`] is turned around the
+//! y axis to [`SpriteSheetSettings::angles`] evenly spaced
+//! angles, and every `AnimationPlayer` is stepped through
+//! [`SpriteSheetSettings::frames`] frames at each angle. The
+//! low-res image is captured for each pair and packed into an
+//! atlas with one row per angle and one column per frame.
+//!
+//! ```rust,ignore
+//! let mut app = App::new();
+//! app.add_plugins(headless_plugins())
+//!     .add_plugins(PixelatingPlugin::headless())
+//!     .add_systems(PostStartup, setup_subject);
+//! let mut baker =
+//!     SpriteSheetBaker::new(app, SpriteSheetSettings::default());
+//! baker.bake()?.save("sheets/taxi.png")?;
+//! ```
+//!
+//! The [`PixelatedCamera`](crate::pixelating_plugin::PixelatedCamera)
+//! should clear to a transparent color, so that the sprites
+//! have a transparent background.
+use crate::headless::HeadlessRenderer;
+use bevy::{math::URect, prelude::*};
+use image::{imageops, GenericImage, RgbaImage};
+use serde::Serialize;
+use std::{
+    f32::consts::TAU,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// add BakeSubject to the entities to turn while baking
+#[derive(Component, Default, Clone, Copy)]
+pub struct BakeSubject;
+
+#[derive(Debug, Clone)]
+pub struct SpriteSheetSettings {
+    /// rows of the sheet, evenly spaced around the y axis
+    pub angles: u32,
+    /// columns of the sheet
+    pub frames: u32,
+    /// animation time between two frames, in seconds
+    pub frame_time: f32,
+    /// updates before the first capture, to let assets load
+    pub warmup_frames: u32,
+    /// Crops every sprite to the smallest rect holding all
+    /// non-transparent pixels of every capture. Otherwise the
+    /// sprites are the size of the whole low-res image.
+    pub trim: bool,
+}
+
+impl Default for SpriteSheetSettings {
+    fn default() -> Self {
+        Self {
+            angles: 8,
+            frames: 1,
+            frame_time: 1. / 12.,
+            warmup_frames: 60,
+            trim: true,
+        }
+    }
+}
+
+/// Where each sprite is in the sheet, saved next to it as RON
+#[derive(Debug, Clone, Serialize)]
+pub struct SpriteSheetDescriptor {
+    /// file name of the sheet png
+    pub image: String,
+    pub sprite_width: u32,
+    pub sprite_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub sprites: Vec<SpriteDescriptor>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpriteDescriptor {
+    pub angle: u32,
+    /// rotation of the subjects around the y axis
+    pub degrees: f32,
+    pub frame: u32,
+    /// animation time, in seconds
+    pub time: f32,
+    /// x, y, width and height in the sheet, in pixels
+    pub rect: [u32; 4],
+}
+
+pub struct SpriteSheet {
+    pub image: RgbaImage,
+    pub descriptor: SpriteSheetDescriptor,
+}
+
+#[derive(Debug)]
+pub enum BakeError {
+    /// a capture of the low-res image never arrived
+    Readback {
+        angle: u32,
+        frame: u32,
+    },
+    /// the rendered image can't be converted to rgba
+    Conversion(String),
+    Image(image::ImageError),
+    Ron(ron::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for BakeError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            BakeError::Readback { angle, frame } => write!(
+                f,
+                "angle {angle}, frame {frame} was never read back"
+            ),
+            BakeError::Conversion(error) => {
+                write!(f, "cannot convert capture: {error}")
+            }
+            BakeError::Image(error) => write!(f, "{error}"),
+            BakeError::Ron(error) => {
+                write!(f, "cannot write descriptor: {error}")
+            }
+            BakeError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for BakeError {}
+
+impl From<image::ImageError> for BakeError {
+    fn from(error: image::ImageError) -> Self {
+        BakeError::Image(error)
+    }
+}
+
+/// Renders sprite sheets from a headless pixelated app
+pub struct SpriteSheetBaker {
+    renderer: HeadlessRenderer,
+    pub settings: SpriteSheetSettings,
+}
+
+impl SpriteSheetBaker {
+    /// `app` must render headlessly, see
+    /// [`crate::headless`]
+    pub fn new(
+        app: App,
+        settings: SpriteSheetSettings,
+    ) -> Self {
+        Self {
+            renderer: HeadlessRenderer::new(app),
+            settings,
+        }
+    }
+
+    pub fn app_mut(&mut self) -> &mut App {
+        self.renderer.app_mut()
+    }
+
+    /// Captures every angle and frame and packs them
+    pub fn bake(
+        &mut self,
+    ) -> Result<SpriteSheet, BakeError> {
+        let settings = self.settings.clone();
+        let angles = settings.angles.max(1);
+        let frames = settings.frames.max(1);
+        self.renderer.update(settings.warmup_frames);
+        let rotations = self.subject_rotations();
+
+        let mut captures = vec![];
+        for angle in 0..angles {
+            let radians =
+                angle as f32 * TAU / angles as f32;
+            for frame in 0..frames {
+                let time =
+                    frame as f32 * settings.frame_time;
+                self.pose(&rotations, radians, time);
+                let capture = self
+                    .renderer
+                    .capture()
+                    .ok_or(BakeError::Readback {
+                        angle,
+                        frame,
+                    })?
+                    .try_into_dynamic()
+                    .map_err(|error| {
+                        BakeError::Conversion(
+                            error.to_string(),
+                        )
+                    })?
+                    .to_rgba8();
+                captures.push((
+                    angle,
+                    radians.to_degrees(),
+                    frame,
+                    time,
+                    capture,
+                ));
+            }
+        }
+
+        // every angle and frame was captured
+        let (width, height) = captures[0].4.dimensions();
+        let bounds = settings
+            .trim
+            .then(|| {
+                opaque_bounds(
+                    captures
+                        .iter()
+                        .map(|capture| &capture.4),
+                )
+            })
+            .flatten()
+            .unwrap_or(URect::new(0, 0, width, height));
+        let size = bounds.size();
+
+        let mut image = RgbaImage::new(
+            size.x * frames,
+            size.y * angles,
+        );
+        let mut sprites = vec![];
+        for (angle, degrees, frame, time, capture) in
+            captures
+        {
+            let (x, y) = (frame * size.x, angle * size.y);
+            let sprite = imageops::crop_imm(
+                &capture,
+                bounds.min.x,
+                bounds.min.y,
+                size.x,
+                size.y,
+            )
+            .to_image();
+            image.copy_from(&sprite, x, y)?;
+            sprites.push(SpriteDescriptor {
+                angle,
+                degrees,
+                frame,
+                time,
+                rect: [x, y, size.x, size.y],
+            });
+        }
+
+        Ok(SpriteSheet {
+            image,
+            descriptor: SpriteSheetDescriptor {
+                image: String::new(),
+                sprite_width: size.x,
+                sprite_height: size.y,
+                columns: frames,
+                rows: angles,
+                sprites,
+            },
+        })
+    }
+
+    // the rotations the subjects were spawned with, which the
+    // angles are added to
+    fn subject_rotations(&mut self) -> Vec<(Entity, Quat)> {
+        let world = &mut self.renderer.app_mut().world;
+        world
+            .query_filtered::<(Entity, &Transform), With<BakeSubject>>()
+            .iter(world)
+            .map(|(entity, transform)| {
+                (entity, transform.rotation)
+            })
+            .collect()
+    }
+
+    fn pose(
+        &mut self,
+        rotations: &[(Entity, Quat)],
+        radians: f32,
+        time: f32,
+    ) {
+        let world = &mut self.renderer.app_mut().world;
+        for (entity, rotation) in rotations {
+            if let Some(mut transform) =
+                world.get_mut::<Transform>(*entity)
+            {
+                transform.rotation =
+                    Quat::from_rotation_y(radians)
+                        * *rotation;
+            }
+        }
+        // paused players still apply a changed time
+        for mut player in world
+            .query::<&mut AnimationPlayer>()
+            .iter_mut(world)
+        {
+            player.pause();
+            player.seek_to(time);
+        }
+    }
+}
+
+impl SpriteSheet {
+    /// Writes the sheet to `path` and its descriptor next to
+    /// it, with the extension `ron`. Returns the descriptor's
+    /// path.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<PathBuf, BakeError> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)
+                .map_err(BakeError::Io)?;
+        }
+        self.image.save_with_format(
+            path,
+            image::ImageFormat::Png,
+        )?;
+
+        let mut descriptor = self.descriptor.clone();
+        descriptor.image = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ron = ron::ser::to_string_pretty(
+            &descriptor,
+            ron::ser::PrettyConfig::default(),
+        )
+        .map_err(BakeError::Ron)?;
+        let descriptor_path = path.with_extension("ron");
+        std::fs::write(&descriptor_path, ron)
+            .map_err(BakeError::Io)?;
+        Ok(descriptor_path)
+    }
+}
+
+// The smallest rect holding every non-transparent pixel of
+// all images, `None` if they are all transparent
+fn opaque_bounds<'a>(
+    images: impl Iterator<Item = &'a RgbaImage>,
+) -> Option<URect> {
+    let mut bounds: Option<URect> = None;
+    for image in images {
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if pixel.0[3] == 0 {
+                continue;
+            }
+            let pixel = URect::new(x, y, x + 1, y + 1);
+            bounds = Some(match bounds {
+                Some(bounds) => bounds.union(pixel),
+                None => pixel,
+            });
+        }
+    }
+    bounds
+}
@@ -0,0 +1,619 @@
+//! CPU versions of the color math in `pixelated.wgsl`, for
+//! previewing the quantized colors outside of the shader.
+//!
+//! The functions take and return linear rgb, like the shader
+//! does after lighting. Use [`oklab_from_color`] and
+//! [`color_from_oklab`] to convert Bevy [`Color`]s such as the
+//! ones in [`crate::colors`]. [`pixelate`] runs the whole
+//! quantization of the pixelated material on one color.
+
+// the matrices are copied digit for digit from the shader
+#![allow(clippy::excessive_precision)]
+
+use crate::{PixelPalette, PixelatedExtension};
+use bevy::{math::Mat3, prelude::*};
+
+// oklab functions by Björn Ottosson
+// https://bottosson.github.io/posts/oklab
+// with the matrices used by "mattz"
+// https://www.shadertoy.com/view/WtccD7
+const LINEAR_TO_LMS: Mat3 = Mat3::from_cols_array(&[
+    0.4121656120,
+    0.2118591070,
+    0.0883097947,
+    0.5362752080,
+    0.6807189584,
+    0.2818474174,
+    0.0514575653,
+    0.1074065790,
+    0.6302613616,
+]);
+
+const LMS_TO_OKLAB: Mat3 = Mat3::from_cols_array(&[
+    0.2104542553,
+    1.9779984951,
+    0.0259040371,
+    0.7936177850,
+    -2.4285922050,
+    0.7827717662,
+    -0.0040720468,
+    0.4505937099,
+    -0.8086757660,
+]);
+
+const OKLAB_TO_LMS: Mat3 = Mat3::from_cols_array(&[
+    1.000000000,
+    1.000000000,
+    1.000000000,
+    0.396337777,
+    -0.105561346,
+    -0.089484178,
+    0.215803757,
+    -0.063854173,
+    -1.291485548,
+]);
+
+const LMS_TO_LINEAR: Mat3 = Mat3::from_cols_array(&[
+    4.076724529,
+    -1.268143773,
+    -0.004111989,
+    -3.307216883,
+    2.609332323,
+    -0.703476310,
+    0.230759054,
+    -0.341134429,
+    1.706862569,
+]);
+
+/// `oklab_from_linear` in `pixelated.wgsl`
+pub fn oklab_from_linear(linear: Vec3) -> Vec3 {
+    let lms = LINEAR_TO_LMS * linear;
+    LMS_TO_OKLAB
+        * Vec3::new(
+            lms.x.cbrt(),
+            lms.y.cbrt(),
+            lms.z.cbrt(),
+        )
+}
+
+/// `linear_from_oklab` in `pixelated.wgsl`
+pub fn linear_from_oklab(oklab: Vec3) -> Vec3 {
+    let lms = OKLAB_TO_LMS * oklab;
+    LMS_TO_LINEAR * (lms * lms * lms)
+}
+
+/// `oklab_mix` in `pixelated.wgsl`, by Inigo Quilez
+/// (MIT license, https://www.shadertoy.com/view/ttcyRS)
+pub fn oklab_mix(lin1: Vec3, lin2: Vec3, a: f32) -> Vec3 {
+    let cbrt = |v: Vec3| {
+        Vec3::new(v.x.cbrt(), v.y.cbrt(), v.z.cbrt())
+    };
+    let lms1 = cbrt(LINEAR_TO_LMS * lin1);
+    let lms2 = cbrt(LINEAR_TO_LMS * lin2);
+    let mut lms = lms1.lerp(lms2, a);
+    // gain in the middle (no oklab anymore, but looks better?)
+    lms *= 1.0 + 0.2 * a * (1.0 - a);
+    LMS_TO_LINEAR * (lms * lms * lms)
+}
+
+/// Snaps the OKLab lightness down to one of `steps` bands,
+/// like the shader does with
+/// [`PixelatedExtension::quantize_steps`](crate::PixelatedExtension::quantize_steps)
+pub fn quantize_lightness(
+    linear: Vec3,
+    steps: u32,
+) -> Vec3 {
+    quantize_lightness_dithered(
+        linear,
+        steps,
+        0.,
+        UVec2::ZERO,
+    )
+}
+
+/// [`quantize_lightness`] with the ordered dithering of
+/// [`PixelatedExtension::dither`] at the low-res pixel
+/// `position`
+pub fn quantize_lightness_dithered(
+    linear: Vec3,
+    steps: u32,
+    dither: f32,
+    position: UVec2,
+) -> Vec3 {
+    linear_from_oklab(quantized_oklab(
+        oklab_from_linear(linear),
+        steps as f32,
+        dither,
+        position,
+    ))
+}
+
+fn quantized_oklab(
+    oklab: Vec3,
+    steps: f32,
+    dither: f32,
+    position: UVec2,
+) -> Vec3 {
+    let dither_offset =
+        (bayer_4x4(position) - 0.5) * dither;
+    let lightness =
+        (oklab.x * steps + dither_offset).floor() / steps;
+    Vec3::new(lightness, oklab.y, oklab.z)
+}
+
+/// `bayer_4x4` in `pixelated.wgsl`, the ordered dithering
+/// threshold of a pixel, in [0, 1)
+pub fn bayer_4x4(position: UVec2) -> f32 {
+    const BAYER: [f32; 16] = [
+        0., 8., 2., 10., 12., 4., 14., 6., 3., 11., 1., 9.,
+        15., 7., 13., 5.,
+    ];
+    BAYER[(position.y % 4 * 4 + position.x % 4) as usize]
+        / 16.
+}
+
+/// `hue_shifted` in `pixelated.wgsl`. Turns the hue of a
+/// color quantized to `steps` bands by the hue shift settings
+/// of `material`, depending on how many bands it is away from
+/// the band of `base_color`.
+pub fn hue_shifted(
+    quantized: Vec3,
+    base_color: Vec3,
+    steps: u32,
+    material: &PixelatedExtension,
+) -> Vec3 {
+    linear_from_oklab(hue_shifted_oklab(
+        oklab_from_linear(quantized),
+        base_color,
+        steps as f32,
+        material,
+    ))
+}
+
+fn hue_shifted_oklab(
+    oklab: Vec3,
+    base_color: Vec3,
+    steps: f32,
+    material: &PixelatedExtension,
+) -> Vec3 {
+    if material.hue_shift == 0.
+        && material.chroma_shift == 0.
+    {
+        return oklab;
+    }
+    let base_band =
+        (oklab_from_linear(base_color).x * steps).floor();
+    // whole bands, as the lightness is quantized
+    let bands = (oklab.x * steps).round() - base_band;
+    let mut lch = oklch_from_oklab(oklab);
+    let shift =
+        material.hue_shift.to_radians() * bands.abs();
+    if bands < 0. {
+        lch.z = turn_toward(
+            lch.z,
+            material.shadow_hue.to_radians(),
+            shift,
+        );
+        lch.y = (lch.y
+            * (1. - material.chroma_shift * bands))
+            .max(0.);
+    } else if bands > 0. {
+        lch.z = turn_toward(
+            lch.z,
+            material.highlight_hue.to_radians(),
+            shift,
+        );
+    }
+    oklab_from_oklch(lch)
+}
+
+// turns a hue toward another along the shorter way, by at most
+// max_angle radians
+fn turn_toward(hue: f32, goal: f32, max_angle: f32) -> f32 {
+    let delta =
+        (goal - hue).sin().atan2((goal - hue).cos());
+    hue + delta.clamp(-max_angle, max_angle)
+}
+
+/// Lightness, chroma and hue in radians
+pub fn oklch_from_oklab(oklab: Vec3) -> Vec3 {
+    Vec3::new(
+        oklab.x,
+        Vec2::new(oklab.y, oklab.z).length(),
+        oklab.z.atan2(oklab.y),
+    )
+}
+
+pub fn oklab_from_oklch(lch: Vec3) -> Vec3 {
+    Vec3::new(
+        lch.x,
+        lch.y * lch.z.cos(),
+        lch.y * lch.z.sin(),
+    )
+}
+
+/// The palette color closest to `linear`, measured in OKLab.
+/// Returns `linear` unchanged for an empty palette.
+pub fn snap_to_palette(
+    linear: Vec3,
+    palette: &[Vec3],
+) -> Vec3 {
+    palette_index(oklab_from_linear(linear), palette)
+        .map_or(linear, |index| palette[index])
+}
+
+// the index of the palette color closest to an oklab color
+fn palette_index(
+    oklab: Vec3,
+    palette: &[Vec3],
+) -> Option<usize> {
+    (0..palette.len()).min_by(|a, b| {
+        let a = oklab_from_linear(palette[*a])
+            .distance_squared(oklab);
+        let b = oklab_from_linear(palette[*b])
+            .distance_squared(oklab);
+        a.total_cmp(&b)
+    })
+}
+
+/// `swap_palette_color` in `pixelated.wgsl`. Moves a palette
+/// color toward the `swap` color with the same index, by
+/// `amount` in OKLab. Colors without a replacement are kept.
+pub fn swap_palette_color(
+    linear: Vec3,
+    palette: &[Vec3],
+    swap: &[Vec3],
+    amount: f32,
+) -> Vec3 {
+    linear_from_oklab(swapped_oklab(
+        oklab_from_linear(linear),
+        palette,
+        swap,
+        amount,
+    ))
+}
+
+fn swapped_oklab(
+    oklab: Vec3,
+    palette: &[Vec3],
+    swap: &[Vec3],
+    amount: f32,
+) -> Vec3 {
+    if swap.is_empty() || amount <= 0. {
+        return oklab;
+    }
+    match palette_index(oklab, palette) {
+        Some(index) if index < swap.len() => oklab
+            .lerp(oklab_from_linear(swap[index]), amount),
+        _ => oklab,
+    }
+}
+
+/// The quantization of the pixelated material, in the order
+/// of its fragment shader: dithered lightness bands, hue
+/// shift, palette snapping and palette swap. `linear` is the
+/// lit color, `base_color` the material's base color and
+/// `position` the low-res pixel, which picks the dithering
+/// threshold.
+pub fn pixelate(
+    linear: Vec3,
+    base_color: Vec3,
+    position: UVec2,
+    material: &PixelatedExtension,
+) -> Vec3 {
+    let steps = material.quantize_steps as f32;
+    let quantized = quantized_oklab(
+        oklab_from_linear(linear),
+        steps,
+        material.dither,
+        position,
+    );
+    let shifted = hue_shifted_oklab(
+        quantized, base_color, steps, material,
+    );
+    let palette = palette_colors(&material.palette);
+    let snapped = match palette_index(shifted, &palette) {
+        Some(index) => oklab_from_linear(palette[index]),
+        None => shifted,
+    };
+    linear_from_oklab(swapped_oklab(
+        snapped,
+        &palette,
+        &palette_colors(&material.palette_swap),
+        material.palette_swap_amount,
+    ))
+}
+
+/// The linear rgb of the colors of a [`PixelPalette`]
+pub fn palette_colors(palette: &PixelPalette) -> Vec<Vec3> {
+    palette
+        .colors
+        .iter()
+        .take(palette.count as usize)
+        .map(|color| color.truncate())
+        .collect()
+}
+
+/// The linear rgb of a [`Color`], ignoring alpha
+pub fn linear_from_color(color: Color) -> Vec3 {
+    let [r, g, b, _] = color.as_linear_rgba_f32();
+    Vec3::new(r, g, b)
+}
+
+pub fn oklab_from_color(color: Color) -> Vec3 {
+    oklab_from_linear(linear_from_color(color))
+}
+
+pub fn color_from_oklab(oklab: Vec3) -> Color {
+    let linear = linear_from_oklab(oklab);
+    Color::rgb_linear(linear.x, linear.y, linear.z)
+}
+
+/// The linear rgb of every color of a palette, for
+/// [`snap_to_palette`]
+pub fn linear_palette(colors: &[Color]) -> Vec<Vec3> {
+    colors.iter().copied().map(linear_from_color).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors;
+
+    const SHADER: &str =
+        include_str!("../assets/pixelated.wgsl");
+
+    // Reads the matrix `name` from pixelated.wgsl. WGSL and
+    // glam both list matrices column by column.
+    fn shader_matrix(name: &str) -> Mat3 {
+        let start = SHADER
+            .find(&format!("let {name}"))
+            .unwrap_or_else(|| {
+                panic!("{name} is not in the shader")
+            });
+        let rest = &SHADER[start..];
+        let open = rest.find("= mat3x3<f32>(").unwrap()
+            + "= mat3x3<f32>(".len();
+        let close = open + rest[open..].find(')').unwrap();
+        let values: Vec<f32> = rest[open..close]
+            .split(',')
+            .map(|value| value.trim().parse().unwrap())
+            .collect();
+        Mat3::from_cols_slice(&values)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{a} != {b}");
+    }
+
+    #[test]
+    fn matrices_match_shader() {
+        assert_eq!(shader_matrix("im1"), LINEAR_TO_LMS);
+        assert_eq!(shader_matrix("im2"), LMS_TO_OKLAB);
+        assert_eq!(shader_matrix("m1"), OKLAB_TO_LMS);
+        assert_eq!(shader_matrix("m2"), LMS_TO_LINEAR);
+        assert!(shader_matrix("kCONEtoLMS")
+            .abs_diff_eq(LINEAR_TO_LMS, 1e-9));
+        assert!(shader_matrix("kLMStoCONE")
+            .abs_diff_eq(LMS_TO_LINEAR, 1e-6));
+    }
+
+    #[test]
+    fn white_and_black() {
+        // the rounded matrices put white slightly off the
+        // lightness axis
+        let white = oklab_from_linear(Vec3::ONE);
+        assert!(
+            white.abs_diff_eq(Vec3::X, 1e-3),
+            "{white}"
+        );
+        assert_close(
+            oklab_from_linear(Vec3::ZERO),
+            Vec3::ZERO,
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for color in [
+            colors::RED,
+            colors::GREEN,
+            colors::BLUE,
+            colors::CRUST,
+        ] {
+            let linear = linear_from_color(color);
+            assert_close(
+                linear_from_oklab(oklab_from_linear(
+                    linear,
+                )),
+                linear,
+            );
+        }
+    }
+
+    #[test]
+    fn quantized_lightness_is_banded() {
+        let linear = linear_from_color(colors::PEACH);
+        let quantized = quantize_lightness(linear, 5);
+        let lightness = oklab_from_linear(quantized).x;
+        let band = lightness * 5.;
+        assert!((band - band.round()).abs() < 1e-3);
+        assert!(lightness <= oklab_from_linear(linear).x);
+    }
+
+    #[test]
+    fn mix_end_points() {
+        let a = linear_from_color(colors::MAUVE);
+        let b = linear_from_color(colors::TEAL);
+        assert_close(oklab_mix(a, b, 0.), a);
+        assert_close(oklab_mix(a, b, 1.), b);
+    }
+
+    #[test]
+    fn converts_palette() {
+        for color in colors::MOCHA {
+            let converted =
+                color_from_oklab(oklab_from_color(color));
+            assert_close(
+                linear_from_color(converted),
+                linear_from_color(color),
+            );
+        }
+    }
+
+    #[test]
+    fn snaps_to_nearest() {
+        let palette =
+            linear_palette(&[colors::CRUST, colors::TEXT]);
+        assert_eq!(
+            snap_to_palette(Vec3::splat(0.9), &palette),
+            palette[1]
+        );
+        assert_eq!(
+            snap_to_palette(Vec3::splat(0.01), &palette),
+            palette[0]
+        );
+    }
+
+    #[test]
+    fn bayer_matches_shader() {
+        let start = SHADER.find("array<f32, 16>(").unwrap()
+            + "array<f32, 16>(".len();
+        let end =
+            start + SHADER[start..].find(')').unwrap();
+        let values: Vec<f32> = SHADER[start..end]
+            .split(',')
+            .map(|value| value.trim().parse().unwrap())
+            .collect();
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(
+                    bayer_4x4(UVec2::new(x, y) + 4),
+                    values[(y * 4 + x) as usize] / 16.
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dither_stays_within_a_band() {
+        let linear = linear_from_color(colors::PEACH);
+        let banded = oklab_from_linear(quantize_lightness(
+            linear, 5,
+        ))
+        .x;
+        for x in 0..4 {
+            let position = UVec2::new(x, 1);
+            assert_close(
+                quantize_lightness_dithered(
+                    linear, 5, 0., position,
+                ),
+                quantize_lightness(linear, 5),
+            );
+            let dithered = oklab_from_linear(
+                quantize_lightness_dithered(
+                    linear, 5, 1., position,
+                ),
+            )
+            .x;
+            // at most the neighbouring band
+            assert!(
+                (dithered - banded).abs() <= 0.2 + 1e-3
+            );
+        }
+    }
+
+    #[test]
+    fn shadow_bands_turn_toward_shadow_hue() {
+        let material = PixelatedExtension {
+            hue_shift: 10.,
+            ..default()
+        };
+        let base_color = linear_from_color(colors::PEACH);
+        let shadow =
+            quantize_lightness(base_color * 0.2, 5);
+        let shifted =
+            hue_shifted(shadow, base_color, 5, &material);
+        let hue = |linear| {
+            oklch_from_oklab(oklab_from_linear(linear))
+                .z
+                .to_degrees()
+        };
+        let turned = hue(shifted) - hue(shadow);
+        let goal = material.shadow_hue - hue(shadow);
+        // at most 10 degrees per band, toward the shadow hue
+        assert!(turned != 0.);
+        assert!(turned.abs() <= 10. * 5. + 1e-3);
+        assert_eq!(
+            turned.signum(),
+            goal.to_radians().sin().signum()
+        );
+        assert_close(
+            hue_shifted(
+                shadow,
+                base_color,
+                5,
+                &PixelatedExtension::default(),
+            ),
+            shadow,
+        );
+    }
+
+    #[test]
+    fn swaps_palette_colors() {
+        let palette =
+            linear_palette(&[colors::CRUST, colors::TEXT]);
+        let swap = linear_palette(&[colors::RED]);
+        assert_close(
+            swap_palette_color(
+                palette[0], &palette, &swap, 1.,
+            ),
+            swap[0],
+        );
+        // TEXT has no replacement
+        assert_close(
+            swap_palette_color(
+                palette[1], &palette, &swap, 1.,
+            ),
+            palette[1],
+        );
+        assert_close(
+            swap_palette_color(
+                palette[0], &palette, &swap, 0.,
+            ),
+            palette[0],
+        );
+    }
+
+    #[test]
+    fn pixelate_snaps_then_swaps() {
+        let material = PixelatedExtension {
+            palette: PixelPalette::new(&[
+                colors::CRUST,
+                colors::TEXT,
+            ]),
+            palette_swap: PixelPalette::new(&[colors::RED]),
+            palette_swap_amount: 1.,
+            ..default()
+        };
+        let base_color = linear_from_color(colors::TEXT);
+        assert_close(
+            pixelate(
+                Vec3::splat(0.01),
+                base_color,
+                UVec2::ZERO,
+                &material,
+            ),
+            linear_from_color(colors::RED),
+        );
+        assert_close(
+            pixelate(
+                Vec3::splat(0.9),
+                base_color,
+                UVec2::ZERO,
+                &material,
+            ),
+            linear_from_color(colors::TEXT),
+        );
+    }
+}
@@ -27,3 +27,11 @@ pub const SURFACE0: Color = Color::hsl(237., 0.16, 0.23);
 pub const BASE: Color = Color::hsl(240., 0.21, 0.15);
 pub const MANTLE: Color = Color::hsl(240., 0.21, 0.12);
 pub const CRUST: Color = Color::hsl(240., 0.23, 0.09);
+
+/// every color of the palette, in catppuccin's order
+pub const MOCHA: [Color; 26] = [
+    ROSEWATER, FLAMINGO, PINK, MAUVE, RED, MAROON, PEACH,
+    YELLOW, GREEN, TEAL, SKY, SAPPHIRE, BLUE, LAVENDER,
+    TEXT, SUBTEXT1, SUBTEXT0, OVERLAY2, OVERLAY1, OVERLAY0,
+    SURFACE2, SURFACE1, SURFACE0, BASE, MANTLE, CRUST,
+];
@@ -0,0 +1,74 @@
+//! Switches every pixelated material to one of the
+//! [`PixelDebugView`]s, to see the prepasses and outlines the
+//! effect is built from.
+use crate::{style::PixelDebugView, PixelatedExtension};
+use bevy::{pbr::ExtendedMaterial, prelude::*};
+
+/// Cycles through the debug views when
+/// [`PixelatedDebug::key`] is pressed. Needs the materials of
+/// the
+/// [`PixelatedMaterialPlugin`](crate::pixelating_plugin::PixelatedMaterialPlugin).
+pub struct PixelatedDebugPlugin;
+
+impl Plugin for PixelatedDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PixelatedDebug>().add_systems(
+            Update,
+            (debug_view_on_key, apply_debug_view).chain(),
+        );
+    }
+}
+
+/// The debug view shown by every pixelated material.
+/// Changing it overrides the debug view of their styles.
+#[derive(Resource, Debug, Clone)]
+pub struct PixelatedDebug {
+    /// `None` only changes the view through this resource
+    pub key: Option<KeyCode>,
+    pub view: PixelDebugView,
+}
+
+impl Default for PixelatedDebug {
+    fn default() -> Self {
+        Self {
+            key: Some(KeyCode::F3),
+            view: PixelDebugView::None,
+        }
+    }
+}
+
+fn debug_view_on_key(
+    input: Res<Input<KeyCode>>,
+    mut pixelated_debug: ResMut<PixelatedDebug>,
+) {
+    if pixelated_debug
+        .key
+        .is_some_and(|key| input.just_pressed(key))
+    {
+        pixelated_debug.view = pixelated_debug.view.next();
+        info!(
+            "Pixelated debug view: {:?}",
+            pixelated_debug.view
+        );
+    }
+}
+
+fn apply_debug_view(
+    debug: Res<PixelatedDebug>,
+    mut materials: ResMut<
+        Assets<
+            ExtendedMaterial<
+                StandardMaterial,
+                PixelatedExtension,
+            >,
+        >,
+    >,
+) {
+    // the initial view leaves the styles' debug views alone
+    if !debug.is_changed() || debug.is_added() {
+        return;
+    }
+    for (_, material) in materials.iter_mut() {
+        material.extension.debug_view = debug.view as u32;
+    }
+}
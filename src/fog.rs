@@ -0,0 +1,113 @@
+//! Fog in the pixel-art style.
+//!
+//! Bevy's fog is applied after the lightness quantization,
+//! which brings smooth gradients back into the pixelated
+//! image. With a [`PixelatedFog`] next to the `FogSettings` of
+//! the [`PixelatedCamera`], the pixelated materials apply the
+//! fog before quantizing instead, so that fogged colors fall
+//! into the same lightness bands and palette colors as the
+//! rest of the image.
+//!
+//! ```rust,ignore
+//! commands.spawn((
+//!     Camera3dBundle::default(),
+//!     PixelatedCamera::default(),
+//!     FogSettings {
+//!         color: colors::BASE,
+//!         falloff: FogFalloff::Linear {
+//!             start: 10.,
+//!             end: 40.,
+//!         },
+//!         ..default()
+//!     },
+//!     PixelatedFog { steps: 4 },
+//! ));
+//! ```
+use crate::{
+    pixelating_plugin::PixelatedCamera, PixelatedExtension,
+};
+use bevy::{pbr::ExtendedMaterial, prelude::*};
+
+/// Copies the [`PixelatedFog`] of the [`PixelatedCamera`]
+/// into the pixelated materials
+pub struct PixelatedFogPlugin;
+
+impl Plugin for PixelatedFogPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, apply_pixelated_fog);
+    }
+}
+
+/// add PixelatedFog to the [`PixelatedCamera`] to quantize
+/// the fog of its `FogSettings` along with the lighting.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct PixelatedFog {
+    /// Amounts of fog the distance is rounded to, so that
+    /// colors fade into the fog color in discrete steps. 0
+    /// keeps the fog smooth, though it is still quantized
+    /// into lightness bands.
+    pub steps: u32,
+}
+
+type PixelatedMaterial =
+    ExtendedMaterial<StandardMaterial, PixelatedExtension>;
+
+// Copies the camera's fog into every material when it
+// changes, and into new materials
+fn apply_pixelated_fog(
+    mut material_events: EventReader<
+        AssetEvent<PixelatedMaterial>,
+    >,
+    mut materials: ResMut<Assets<PixelatedMaterial>>,
+    cameras: Query<
+        Ref<PixelatedFog>,
+        With<PixelatedCamera>,
+    >,
+    mut removed: RemovedComponents<PixelatedFog>,
+) {
+    let fog = cameras.get_single().ok();
+    let new_materials: Vec<AssetId<PixelatedMaterial>> =
+        material_events
+            .read()
+            .filter_map(|event| match event {
+                AssetEvent::Added { id } => Some(*id),
+                _ => None,
+            })
+            .collect();
+    let fog_changed =
+        fog.as_ref().is_some_and(|fog| fog.is_changed())
+            || removed.read().count() > 0;
+
+    if !fog_changed && new_materials.is_empty() {
+        return;
+    }
+    let (pixelated_fog, fog_steps) = match fog {
+        Some(fog) => (1, fog.steps),
+        None => (0, 0),
+    };
+    let ids: Vec<AssetId<PixelatedMaterial>> =
+        if fog_changed {
+            materials.ids().collect()
+        } else {
+            new_materials
+        };
+    for id in ids {
+        // read first, so that unchanged materials aren't
+        // marked as modified
+        let unchanged =
+            materials.get(id).is_none_or(|material| {
+                material.extension.pixelated_fog
+                    == pixelated_fog
+                    && material.extension.fog_steps
+                        == fog_steps
+            });
+        if unchanged {
+            continue;
+        }
+        if let Some(material) = materials.get_mut(id) {
+            material.extension.pixelated_fog =
+                pixelated_fog;
+            material.extension.fog_steps = fog_steps;
+        }
+    }
+}
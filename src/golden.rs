@@ -0,0 +1,294 @@
+//! Compares rendered frames against stored golden images.
+//!
+//! A missing golden image is an error, so that a fresh clone
+//! or CI never passes without comparing anything. Setting the
+//! `PIXELATE_BLESS` environment variable writes missing golden
+//! images and overwrites existing ones after an intended change
+//! to the look. When a comparison fails, the rendered frame is
+//! saved next to the golden image as `<name>.actual.png` for
+//! inspection.
+use bevy::prelude::*;
+use image::RgbaImage;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable that writes and overwrites golden
+/// images
+pub const BLESS_ENV: &str = "PIXELATE_BLESS";
+
+/// How much a frame may differ from its golden image
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// largest difference of any channel for a pixel to
+    /// still count as matching
+    pub per_channel: u8,
+    /// number of pixels allowed to not match
+    pub mismatched_pixels: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            // software and hardware rasterizers round
+            // slightly differently
+            per_channel: 2,
+            mismatched_pixels: 0,
+        }
+    }
+}
+
+/// How two images of the same size differ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImageDifference {
+    pub mismatched_pixels: usize,
+    pub max_difference: u8,
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Image(image::ImageError),
+    /// the rendered image can't be converted to rgba
+    Conversion(String),
+    /// there is no golden image yet, the rendered frame is
+    /// saved to `actual_path`
+    Missing {
+        path: PathBuf,
+        actual_path: PathBuf,
+    },
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Mismatch {
+        difference: ImageDifference,
+        actual_path: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            GoldenError::Image(error) => write!(f, "{error}"),
+            GoldenError::Conversion(error) => {
+                write!(f, "cannot convert frame: {error}")
+            }
+            GoldenError::Missing { path, actual_path } => {
+                write!(
+                    f,
+                    "no golden image at {}, frame saved to {}; \
+                     set {BLESS_ENV} to keep it",
+                    path.display(),
+                    actual_path.display()
+                )
+            }
+            GoldenError::SizeMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "expected a {}x{} frame, rendered {}x{}",
+                    expected.0, expected.1, actual.0, actual.1
+                )
+            }
+            GoldenError::Mismatch {
+                difference,
+                actual_path,
+            } => write!(
+                f,
+                "{} pixels differ by up to {}, frame saved to {}",
+                difference.mismatched_pixels,
+                difference.max_difference,
+                actual_path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(error: image::ImageError) -> Self {
+        GoldenError::Image(error)
+    }
+}
+
+/// Compares two images pixel by pixel
+pub fn compare_images(
+    actual: &RgbaImage,
+    expected: &RgbaImage,
+    per_channel: u8,
+) -> Result<ImageDifference, GoldenError> {
+    if actual.dimensions() != expected.dimensions() {
+        return Err(GoldenError::SizeMismatch {
+            expected: expected.dimensions(),
+            actual: actual.dimensions(),
+        });
+    }
+    let mut difference = ImageDifference::default();
+    for (a, e) in actual.pixels().zip(expected.pixels()) {
+        let max =
+            a.0.iter()
+                .zip(e.0)
+                .map(|(a, e)| a.abs_diff(e))
+                .max()
+                .unwrap_or(0);
+        difference.max_difference =
+            difference.max_difference.max(max);
+        if max > per_channel {
+            difference.mismatched_pixels += 1;
+        }
+    }
+    Ok(difference)
+}
+
+/// Compares a frame, for example from
+/// [`HeadlessRenderer::capture`](crate::headless::HeadlessRenderer::capture),
+/// against the golden png at `path`.
+pub fn check_golden(
+    frame: Image,
+    path: impl AsRef<Path>,
+    tolerance: Tolerance,
+) -> Result<(), GoldenError> {
+    let path = path.as_ref();
+    let actual = frame
+        .try_into_dynamic()
+        .map_err(|error| {
+            GoldenError::Conversion(error.to_string())
+        })?
+        .to_rgba8();
+
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .map_err(image::ImageError::IoError)?;
+    }
+    let actual_path = path.with_extension("actual.png");
+    if std::env::var_os(BLESS_ENV).is_some() {
+        actual.save_with_format(
+            path,
+            image::ImageFormat::Png,
+        )?;
+        info!("Golden image written to {}", path.display());
+        return Ok(());
+    }
+    if !path.exists() {
+        actual.save_with_format(
+            &actual_path,
+            image::ImageFormat::Png,
+        )?;
+        return Err(GoldenError::Missing {
+            path: path.to_path_buf(),
+            actual_path,
+        });
+    }
+
+    let expected = image::open(path)?.to_rgba8();
+    let difference = compare_images(
+        &actual,
+        &expected,
+        tolerance.per_channel,
+    )?;
+    if difference.mismatched_pixels
+        <= tolerance.mismatched_pixels
+    {
+        return Ok(());
+    }
+    actual.save_with_format(
+        &actual_path,
+        image::ImageFormat::Png,
+    )?;
+    Err(GoldenError::Mismatch {
+        difference,
+        actual_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn filled(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(4, 3, Rgba(color))
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = filled([10, 20, 30, 255]);
+        assert_eq!(
+            compare_images(&image, &image, 0).unwrap(),
+            ImageDifference::default()
+        );
+    }
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let expected = filled([10, 20, 30, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 1, Rgba([12, 19, 30, 255]));
+        let difference =
+            compare_images(&actual, &expected, 2).unwrap();
+        assert_eq!(difference.mismatched_pixels, 0);
+        assert_eq!(difference.max_difference, 2);
+    }
+
+    #[test]
+    fn differences_beyond_tolerance_are_counted() {
+        let expected = filled([10, 20, 30, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(0, 0, Rgba([13, 20, 30, 255]));
+        actual.put_pixel(3, 2, Rgba([10, 20, 30, 0]));
+        let difference =
+            compare_images(&actual, &expected, 2).unwrap();
+        assert_eq!(difference.mismatched_pixels, 2);
+        assert_eq!(difference.max_difference, 255);
+    }
+
+    #[test]
+    fn different_sizes_are_an_error() {
+        let expected = filled([0, 0, 0, 255]);
+        let actual = RgbaImage::new(3, 4);
+        assert!(matches!(
+            compare_images(&actual, &expected, 255),
+            Err(GoldenError::SizeMismatch {
+                expected: (4, 3),
+                actual: (3, 4),
+            })
+        ));
+    }
+
+    #[test]
+    fn default_tolerance_allows_rounding_only() {
+        let tolerance = Tolerance::default();
+        assert_eq!(tolerance.per_channel, 2);
+        assert_eq!(tolerance.mismatched_pixels, 0);
+
+        let expected = filled([100, 100, 100, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(2, 1, Rgba([102, 98, 100, 255]));
+        let difference = compare_images(
+            &actual,
+            &expected,
+            tolerance.per_channel,
+        )
+        .unwrap();
+        assert!(
+            difference.mismatched_pixels
+                <= tolerance.mismatched_pixels
+        );
+
+        actual.put_pixel(2, 1, Rgba([103, 100, 100, 255]));
+        let difference = compare_images(
+            &actual,
+            &expected,
+            tolerance.per_channel,
+        )
+        .unwrap();
+        assert!(
+            difference.mismatched_pixels
+                > tolerance.mismatched_pixels
+        );
+    }
+}
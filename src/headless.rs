@@ -0,0 +1,110 @@
+//! Renders the pixelated scene without a window, for example
+//! to regression test the look in CI with [`crate::golden`].
+//!
+//! ```rust,ignore
+//! let mut app = App::new();
+//! app.add_plugins(headless_plugins())
+//!     .add_plugins(PixelatingPlugin::headless())
+//!     .add_systems(PostStartup, setup_scene);
+//! let mut renderer = HeadlessRenderer::new(app);
+//! renderer.update(10);
+//! let image = renderer.capture().unwrap();
+//! ```
+//!
+//! Machines without a GPU can use a software rasterizer such
+//! as Mesa's lavapipe or llvmpipe, selected with the
+//! `WGPU_BACKEND` environment variable.
+use crate::{
+    pixelating_plugin::FirstPassImage,
+    readback::ImageReadbackManager,
+};
+use bevy::{
+    app::{
+        PluginGroupBuilder, PluginsState,
+        ScheduleRunnerPlugin,
+    },
+    prelude::*,
+    tasks::tick_global_task_pools_on_main_thread,
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use std::time::Duration;
+
+/// How many frames [`HeadlessRenderer::capture`] waits for
+/// the readback before giving up
+const MAX_CAPTURE_FRAMES: u32 = 60;
+
+/// [`DefaultPlugins`] without a window, ticking at 60fps when
+/// the app is run instead of updated manually.
+pub fn headless_plugins() -> PluginGroupBuilder {
+    DefaultPlugins
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .set(ImagePlugin::default_nearest())
+        .disable::<WinitPlugin>()
+        .add(ScheduleRunnerPlugin::run_loop(
+            Duration::from_secs_f64(1. / 60.),
+        ))
+}
+
+/// Drives a headless app frame by frame and reads the
+/// low-res image back to the CPU.
+pub struct HeadlessRenderer {
+    app: App,
+}
+
+impl HeadlessRenderer {
+    /// Finishes building the app, waiting for the renderer to
+    /// be created.
+    pub fn new(mut app: App) -> Self {
+        while app.plugins_state() == PluginsState::Adding {
+            tick_global_task_pools_on_main_thread();
+        }
+        app.finish();
+        app.cleanup();
+        Self { app }
+    }
+
+    pub fn app_mut(&mut self) -> &mut App {
+        &mut self.app
+    }
+
+    /// Runs `frames` updates, for example to let assets load
+    /// and the scene settle before capturing
+    pub fn update(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.app.update();
+        }
+    }
+
+    /// Renders until the low-res image has been read back.
+    /// Returns `None` if the readback doesn't arrive.
+    pub fn capture(&mut self) -> Option<Image> {
+        let (tx, rx) = async_channel::bounded(1);
+        let image = self
+            .app
+            .world
+            .resource::<FirstPassImage>()
+            .0
+            .clone();
+        self.app
+            .world
+            .resource_mut::<ImageReadbackManager>()
+            .read_image(image, move |image| {
+                tx.try_send(image).ok();
+            });
+        for _ in 0..MAX_CAPTURE_FRAMES {
+            self.app.update();
+            if let Ok(image) = rx.try_recv() {
+                return Some(image);
+            }
+        }
+        // the readback task may still finish after the
+        // last update
+        std::thread::sleep(Duration::from_millis(100));
+        rx.try_recv().ok()
+    }
+}
@@ -0,0 +1,701 @@
+//! Full resolution 3d objects that depth-test against the
+//! pixelated scene.
+//!
+//! The low-res prepass depth of the [`PixelatedCamera`] is
+//! stored in the `FirstPassDepthImage`, then upscaled with
+//! nearest neighbor sampling into the depth buffer of every
+//! [`PixelatedHybridCamera`] before its main pass.
+use crate::pixelating_plugin::{
+    display_rect, FirstPassDepthImage, MainPassDisplay,
+    PixelatedCamera, PixelatedCompositing,
+    PixelatedOutputCamera, PixelatedOverlayCamera,
+};
+use bevy::{
+    core_pipeline::{
+        core_3d::{
+            self, Camera3dDepthLoadOp, CORE_3D_DEPTH_FORMAT,
+        },
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+        prepass::ViewPrepassTextures,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        camera::{ExtractedCamera, ScalingMode, Viewport},
+        extract_component::{
+            ExtractComponent, ExtractComponentPlugin,
+        },
+        extract_resource::ExtractResourcePlugin,
+        render_asset::RenderAssets,
+        render_graph::{
+            NodeRunError, RenderGraphApp,
+            RenderGraphContext, ViewNode, ViewNodeRunner,
+        },
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        view::ViewDepthTexture,
+        Render, RenderApp, RenderSet,
+    },
+    transform::TransformSystem,
+};
+
+/// add PixelatedHybridCamera to a 3d camera to render its
+/// content at full resolution, occluded by the pixelated scene.
+///
+/// The camera follows the [`PixelatedCamera`] and covers the
+/// area of the window the pixelated image is displayed in.
+/// It is composited as a [`PixelatedOverlayCamera`], and
+/// requires [`PixelatedCompositing::ColorAndDepth`].
+///
+/// Hybrid cameras must not use any prepass, as the prepass
+/// would overwrite the upscaled depth.
+#[derive(Component, Default, Clone, ExtractComponent)]
+pub struct PixelatedHybridCamera;
+
+pub struct HybridDepthPlugin;
+
+impl Plugin for HybridDepthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<PixelatedCamera>::default(),
+            ExtractComponentPlugin::<PixelatedHybridCamera>::default(),
+            ExtractResourcePlugin::<PixelatedCompositing>::default(),
+            ExtractResourcePlugin::<FirstPassDepthImage>::default(),
+        ))
+        .add_systems(Update, configure_hybrid_cameras)
+        .add_systems(
+            PostUpdate,
+            follow_pixelated_camera
+                .before(TransformSystem::TransformPropagate),
+        );
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .add_systems(
+                Render,
+                prepare_depth_pipelines
+                    .in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<
+                StoreLowResDepthNode,
+            >>(
+                core_3d::graph::NAME,
+                StoreLowResDepthNode::NAME,
+            )
+            .add_render_graph_node::<ViewNodeRunner<
+                UpscaleDepthNode,
+            >>(
+                core_3d::graph::NAME,
+                UpscaleDepthNode::NAME,
+            )
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::END_PREPASSES,
+                    StoreLowResDepthNode::NAME,
+                    core_3d::graph::node::START_MAIN_PASS,
+                ],
+            )
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::END_PREPASSES,
+                    UpscaleDepthNode::NAME,
+                    core_3d::graph::node::START_MAIN_PASS,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .init_resource::<PixelatedDepthPipelines>()
+            .init_resource::<SpecializedRenderPipelines<
+                PixelatedDepthPipelines,
+            >>();
+    }
+}
+
+// Keeps the upscaled depth loaded into the main pass, and
+// composites the camera as an overlay
+fn configure_hybrid_cameras(
+    mut commands: Commands,
+    mut cameras: Query<
+        (
+            Entity,
+            &mut Camera3d,
+            Has<PixelatedOverlayCamera>,
+        ),
+        Added<PixelatedHybridCamera>,
+    >,
+) {
+    for (entity, mut camera_3d, is_overlay) in &mut cameras
+    {
+        camera_3d.depth_load_op = Camera3dDepthLoadOp::Load;
+        if !is_overlay {
+            commands
+                .entity(entity)
+                .insert(PixelatedOverlayCamera::default());
+        }
+    }
+}
+
+// Hybrid cameras see the scene exactly like the pixelated
+// camera does, but only in the area the low-res image is
+// displayed in
+#[allow(clippy::type_complexity)]
+fn follow_pixelated_camera(
+    pixelated: Query<
+        (&Transform, &Projection),
+        (
+            With<PixelatedCamera>,
+            Without<PixelatedHybridCamera>,
+        ),
+    >,
+    output: Query<
+        (&Camera, &GlobalTransform),
+        (
+            With<PixelatedOutputCamera>,
+            Without<PixelatedHybridCamera>,
+        ),
+    >,
+    display: Query<&GlobalTransform, With<MainPassDisplay>>,
+    mut hybrid: Query<
+        (&mut Camera, &mut Transform, &mut Projection),
+        With<PixelatedHybridCamera>,
+    >,
+) {
+    let Ok((pixelated_transform, pixelated_projection)) =
+        pixelated.get_single()
+    else {
+        return;
+    };
+    let mut projection = pixelated_projection.clone();
+    // the low-res target and the display area have different
+    // sizes, so window-relative scaling would not match
+    if let Projection::Orthographic(ortho) = &mut projection
+    {
+        ortho.scaling_mode = ScalingMode::Fixed {
+            width: ortho.area.width(),
+            height: ortho.area.height(),
+        };
+        ortho.scale = 1.;
+    }
+
+    let viewport = output.get_single().ok().and_then(
+        |(camera, camera_transform)| {
+            let rect = display_rect(
+                camera,
+                camera_transform,
+                display.get_single().ok()?,
+            )?;
+            let logical_size =
+                camera.logical_viewport_size()?;
+            let physical_size =
+                camera.physical_viewport_size()?.as_vec2();
+            let scale = physical_size / logical_size;
+            let min = (rect.min * scale)
+                .clamp(Vec2::ZERO, physical_size)
+                .as_uvec2();
+            let max = (rect.max * scale)
+                .clamp(Vec2::ZERO, physical_size)
+                .as_uvec2();
+            (min.cmplt(max).all()).then(|| Viewport {
+                physical_position: min,
+                physical_size: max - min,
+                ..default()
+            })
+        },
+    );
+
+    for (
+        mut camera,
+        mut transform,
+        mut hybrid_projection,
+    ) in &mut hybrid
+    {
+        *transform = *pixelated_transform;
+        *hybrid_projection = projection.clone();
+        camera.is_active = viewport.is_some();
+        camera.viewport = viewport.clone();
+    }
+}
+
+// Stores the prepass depth of the pixelated camera in the
+// `FirstPassDepthImage`
+#[derive(Default)]
+struct StoreLowResDepthNode;
+
+impl StoreLowResDepthNode {
+    const NAME: &'static str =
+        "pixelated_store_low_res_depth";
+}
+
+impl ViewNode for StoreLowResDepthNode {
+    type ViewQuery = (
+        &'static ViewPrepassTextures,
+        &'static PixelatedCamera,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (prepass_textures, _): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if *world.resource::<PixelatedCompositing>()
+            != PixelatedCompositing::ColorAndDepth
+        {
+            return Ok(());
+        }
+        let pipelines =
+            world.resource::<PixelatedDepthPipelines>();
+        let Some(ids) = world
+            .get_resource::<PixelatedDepthPipelineIds>()
+        else {
+            return Ok(());
+        };
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(ids.store)
+        else {
+            return Ok(());
+        };
+        let Some(prepass_depth) = &prepass_textures.depth
+        else {
+            return Ok(());
+        };
+        let depth_image =
+            world.resource::<FirstPassDepthImage>();
+        let Some(depth_image) = world
+            .resource::<RenderAssets<Image>>()
+            .get(&depth_image.0)
+        else {
+            return Ok(());
+        };
+
+        let bind_group = render_context
+            .render_device()
+            .create_bind_group(
+                "pixelated_store_low_res_depth_bind_group",
+                pipelines.store_layout(ids.samples),
+                &BindGroupEntries::single(
+                    &prepass_depth.default_view,
+                ),
+            );
+
+        let mut render_pass = render_context
+            .begin_tracked_render_pass(
+            RenderPassDescriptor {
+                label: Some(
+                    "pixelated_store_low_res_depth_pass",
+                ),
+                color_attachments: &[Some(
+                    RenderPassColorAttachment {
+                        view: &depth_image.texture_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(
+                                Default::default(),
+                            ),
+                            store: true,
+                        },
+                    },
+                )],
+                depth_stencil_attachment: None,
+            },
+        );
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+// Writes the upscaled `FirstPassDepthImage` into the depth
+// buffer of a hybrid camera
+#[derive(Default)]
+struct UpscaleDepthNode;
+
+impl UpscaleDepthNode {
+    const NAME: &'static str = "pixelated_upscale_depth";
+}
+
+impl ViewNode for UpscaleDepthNode {
+    type ViewQuery = (
+        &'static ExtractedCamera,
+        &'static ViewDepthTexture,
+        &'static PixelatedHybridCamera,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (camera, depth, _): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if *world.resource::<PixelatedCompositing>()
+            != PixelatedCompositing::ColorAndDepth
+        {
+            return Ok(());
+        }
+        let pipelines =
+            world.resource::<PixelatedDepthPipelines>();
+        let Some(ids) = world
+            .get_resource::<PixelatedDepthPipelineIds>()
+        else {
+            return Ok(());
+        };
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(ids.upscale)
+        else {
+            return Ok(());
+        };
+        let depth_image =
+            world.resource::<FirstPassDepthImage>();
+        let Some(depth_image) = world
+            .resource::<RenderAssets<Image>>()
+            .get(&depth_image.0)
+        else {
+            return Ok(());
+        };
+
+        let bind_group = render_context
+            .render_device()
+            .create_bind_group(
+                "pixelated_upscale_depth_bind_group",
+                &pipelines.upscale_layout,
+                &BindGroupEntries::single(
+                    &depth_image.texture_view,
+                ),
+            );
+
+        let mut render_pass = render_context
+            .begin_tracked_render_pass(
+                RenderPassDescriptor {
+                    label: Some(
+                        "pixelated_upscale_depth_pass",
+                    ),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(
+                        RenderPassDepthStencilAttachment {
+                            view: &depth.view,
+                            depth_ops: Some(Operations {
+                                load: LoadOp::Clear(0.0),
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        },
+                    ),
+                },
+            );
+        if let Some(viewport) = camera.viewport.as_ref() {
+            render_pass.set_camera_viewport(viewport);
+        }
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct PixelatedDepthPipelines {
+    store_layout: BindGroupLayout,
+    // for prepass depth rendered with msaa
+    store_layout_multisampled: BindGroupLayout,
+    upscale_layout: BindGroupLayout,
+    shader: Handle<Shader>,
+}
+
+impl PixelatedDepthPipelines {
+    fn store_layout(
+        &self,
+        samples: u32,
+    ) -> &BindGroupLayout {
+        if samples > 1 {
+            &self.store_layout_multisampled
+        } else {
+            &self.store_layout
+        }
+    }
+}
+
+fn depth_texture_layout(
+    render_device: &RenderDevice,
+    label: &'static str,
+    sample_type: TextureSampleType,
+    multisampled: bool,
+) -> BindGroupLayout {
+    render_device.create_bind_group_layout(
+        &BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type,
+                    view_dimension:
+                        TextureViewDimension::D2,
+                    multisampled,
+                },
+                count: None,
+            }],
+        },
+    )
+}
+
+impl FromWorld for PixelatedDepthPipelines {
+    fn from_world(world: &mut World) -> Self {
+        let render_device =
+            world.resource::<RenderDevice>();
+
+        Self {
+            store_layout: depth_texture_layout(
+                render_device,
+                "pixelated_store_low_res_depth_bind_group_layout",
+                TextureSampleType::Depth,
+                false,
+            ),
+            store_layout_multisampled: depth_texture_layout(
+                render_device,
+                "pixelated_store_low_res_depth_multisampled_bind_group_layout",
+                TextureSampleType::Depth,
+                true,
+            ),
+            upscale_layout: depth_texture_layout(
+                render_device,
+                "pixelated_upscale_depth_bind_group_layout",
+                TextureSampleType::Float { filterable: false },
+                false,
+            ),
+            shader: world
+                .resource::<AssetServer>()
+                .load("pixelated_depth.wgsl"),
+        }
+    }
+}
+
+/// The pipelines depend on the sample count of the global
+/// [`Msaa`], which the depth textures are created with
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum PixelatedDepthPipelineKey {
+    Store { samples: u32 },
+    Upscale { samples: u32 },
+}
+
+impl SpecializedRenderPipeline for PixelatedDepthPipelines {
+    type Key = PixelatedDepthPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+    ) -> RenderPipelineDescriptor {
+        match key {
+            PixelatedDepthPipelineKey::Store {
+                samples,
+            } => {
+                let mut shader_defs =
+                    vec!["STORE_DEPTH".into()];
+                if samples > 1 {
+                    shader_defs.push("MULTISAMPLED".into());
+                }
+                RenderPipelineDescriptor {
+                    label: Some(
+                        "pixelated_store_low_res_depth_pipeline"
+                            .into(),
+                    ),
+                    layout: vec![self
+                        .store_layout(samples)
+                        .clone()],
+                    vertex: fullscreen_shader_vertex_state(),
+                    fragment: Some(FragmentState {
+                        shader: self.shader.clone(),
+                        shader_defs,
+                        entry_point: "fragment".into(),
+                        // the low-res depth is never multisampled
+                        targets: vec![Some(ColorTargetState {
+                            format: TextureFormat::R32Float,
+                            blend: None,
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    push_constant_ranges: vec![],
+                }
+            }
+            PixelatedDepthPipelineKey::Upscale {
+                samples,
+            } => {
+                RenderPipelineDescriptor {
+                    label: Some(
+                        "pixelated_upscale_depth_pipeline"
+                            .into(),
+                    ),
+                    layout: vec![self
+                        .upscale_layout
+                        .clone()],
+                    vertex: fullscreen_shader_vertex_state(
+                    ),
+                    fragment: Some(FragmentState {
+                        shader: self.shader.clone(),
+                        shader_defs: vec![],
+                        entry_point: "fragment".into(),
+                        targets: vec![],
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil: Some(
+                        DepthStencilState {
+                            format: CORE_3D_DEPTH_FORMAT,
+                            depth_write_enabled: true,
+                            depth_compare:
+                                CompareFunction::Always,
+                            stencil: StencilState::default(
+                            ),
+                            bias: DepthBiasState::default(),
+                        },
+                    ),
+                    // every sample of a hybrid camera's depth
+                    // gets the depth of its low-res texel
+                    multisample: MultisampleState {
+                        count: samples,
+                        ..default()
+                    },
+                    push_constant_ranges: vec![],
+                }
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+struct PixelatedDepthPipelineIds {
+    store: CachedRenderPipelineId,
+    upscale: CachedRenderPipelineId,
+    samples: u32,
+}
+
+fn prepare_depth_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    pipelines: Res<PixelatedDepthPipelines>,
+    mut specialized: ResMut<
+        SpecializedRenderPipelines<PixelatedDepthPipelines>,
+    >,
+    msaa: Res<Msaa>,
+) {
+    let samples = msaa.samples();
+    commands.insert_resource(PixelatedDepthPipelineIds {
+        store: specialized.specialize(
+            &pipeline_cache,
+            &pipelines,
+            PixelatedDepthPipelineKey::Store { samples },
+        ),
+        upscale: specialized.specialize(
+            &pipeline_cache,
+            &pipelines,
+            PixelatedDepthPipelineKey::Upscale { samples },
+        ),
+        samples,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::tasks::block_on;
+
+    // A device on any adapter, including software ones, or
+    // `None` on machines without one
+    fn render_device() -> Option<RenderDevice> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            block_on(instance.request_adapter(&default()))?;
+        let (device, _) = block_on(
+            adapter.request_device(&default(), None),
+        )
+        .ok()?;
+        Some(device.into())
+    }
+
+    fn pipelines(
+        render_device: &RenderDevice,
+    ) -> PixelatedDepthPipelines {
+        PixelatedDepthPipelines {
+            store_layout: depth_texture_layout(
+                render_device,
+                "store",
+                TextureSampleType::Depth,
+                false,
+            ),
+            store_layout_multisampled: depth_texture_layout(
+                render_device,
+                "store_multisampled",
+                TextureSampleType::Depth,
+                true,
+            ),
+            upscale_layout: depth_texture_layout(
+                render_device,
+                "upscale",
+                TextureSampleType::Float {
+                    filterable: false,
+                },
+                false,
+            ),
+            shader: Handle::default(),
+        }
+    }
+
+    #[test]
+    fn pipelines_are_specialized_for_the_sample_count() {
+        let Some(render_device) = render_device() else {
+            eprintln!("no adapter, skipping");
+            return;
+        };
+        let pipelines = pipelines(&render_device);
+        for samples in [1, 4] {
+            let store = pipelines.specialize(
+                PixelatedDepthPipelineKey::Store {
+                    samples,
+                },
+            );
+            let multisampled = store
+                .fragment
+                .as_ref()
+                .unwrap()
+                .shader_defs
+                .contains(&"MULTISAMPLED".into());
+            assert_eq!(multisampled, samples > 1);
+            assert_eq!(
+                store.layout[0].id(),
+                pipelines.store_layout(samples).id()
+            );
+            // the low-res depth is never multisampled
+            assert_eq!(store.multisample.count, 1);
+
+            let upscale = pipelines.specialize(
+                PixelatedDepthPipelineKey::Upscale {
+                    samples,
+                },
+            );
+            assert_eq!(upscale.multisample.count, samples);
+        }
+        assert_ne!(
+            pipelines.store_layout(1).id(),
+            pipelines.store_layout(4).id()
+        );
+    }
+}
@@ -1,12 +1,40 @@
+// the `ShaderType` derive of encase 0.6 generates `check` fns
+// that are never called, which newer compilers report
+#![allow(dead_code)]
+
 use bevy::{
     pbr::MaterialExtension,
     prelude::*,
-    render::render_resource::{AsBindGroup, ShaderRef},
+    render::render_resource::{
+        AsBindGroup, ShaderRef, ShaderType,
+    },
 };
+use style::PixelStyle;
+pub mod baker;
+pub mod color;
 pub mod colors;
+pub mod debug;
+pub mod fog;
+pub mod golden;
+pub mod headless;
+pub mod hybrid;
+pub mod palette_swap;
+pub mod pixel_bloom;
+pub mod pixel_density;
+pub mod pixel_lod;
+pub mod pixel_viewport;
 pub mod pixelating_plugin;
+pub mod readback;
+pub mod recorder;
+pub mod retro;
 pub mod rotators;
 pub mod screenshots;
+pub mod sprites;
+pub mod stepped_animation;
+pub mod style;
+
+/// The most colors a [`PixelPalette`] can hold
+pub const MAX_PALETTE_COLORS: usize = 16;
 
 #[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
 pub struct PixelatedExtension {
@@ -14,6 +42,163 @@ pub struct PixelatedExtension {
     // so we start from binding slot 100, leaving slots 0-99 for the base material.
     #[uniform(100)]
     pub quantize_steps: u32,
+    /// how much the depth outline darkens the color
+    #[uniform(100)]
+    pub outline_strength: f32,
+    /// how much the normal edges brighten the color
+    #[uniform(100)]
+    pub normal_edge_strength: f32,
+    /// depth difference, in view space, that counts as an
+    /// outline
+    #[uniform(100)]
+    pub depth_tolerance: f32,
+    /// 0 turns dithering between lightness bands off, 1
+    /// dithers across a whole band
+    #[uniform(100)]
+    pub dither: f32,
+    /// Degrees the OKLCh hue of each band turns, per band away
+    /// from the band of the base color: darker bands toward
+    /// `shadow_hue`, lighter ones toward `highlight_hue`. 0
+    /// turns hue shifting off.
+    #[uniform(100)]
+    pub hue_shift: f32,
+    /// chroma change per band darker than the base color, e.g.
+    /// -0.1 desaturates each shadow band by another 10%
+    #[uniform(100)]
+    pub chroma_shift: f32,
+    /// OKLCh hue shadows turn toward, in degrees
+    #[uniform(100)]
+    pub shadow_hue: f32,
+    /// OKLCh hue highlights turn toward, in degrees
+    #[uniform(100)]
+    pub highlight_hue: f32,
+    /// one of the [`PixelDebugView`](style::PixelDebugView)s
+    #[uniform(100)]
+    pub debug_view: u32,
+    /// width of the depth outline, in low-res texels
+    #[uniform(100)]
+    pub outline_width: u32,
+    /// With a perspective camera, the view distance up to
+    /// which the outline is `outline_width` wide. Further
+    /// away it gets thinner, down to a single texel.
+    #[uniform(100)]
+    pub outline_falloff: f32,
+    /// one of the
+    /// [`TexturePixelation`](style::TexturePixelation) modes
+    /// for the base color texture
+    #[uniform(100)]
+    pub texture_pixelation: u32,
+    /// texels of the base color texture shown per low-res
+    /// pixel when the texture is pixelated
+    #[uniform(100)]
+    pub texel_density: f32,
+    /// added to the mip level the pixelated texture is sampled
+    /// at, positive values blur it
+    #[uniform(100)]
+    pub texture_mip_bias: f32,
+    /// How strong the specular highlight of a directional
+    /// light must be to be drawn, hard-edged, in
+    /// `specular_color`. 0 turns the highlights off.
+    #[uniform(100)]
+    pub specular_threshold: f32,
+    /// the alpha is the opacity of the highlight
+    #[uniform(100)]
+    pub specular_color: Color,
+    /// The share of grazing angles drawn as a rim light band
+    /// in `rim_color`, from 0 for none to 1 for the whole
+    /// surface
+    #[uniform(100)]
+    pub rim_width: f32,
+    /// the alpha is the opacity of the rim light
+    #[uniform(100)]
+    pub rim_color: Color,
+    /// 1 applies the view's fog before quantizing, set from
+    /// the camera's [`PixelatedFog`](fog::PixelatedFog)
+    #[uniform(100)]
+    pub pixelated_fog: u32,
+    /// amounts of fog the distance is rounded to, 0 keeps it
+    /// smooth
+    #[uniform(100)]
+    pub fog_steps: u32,
+    /// How far the palette colors are replaced by the
+    /// `palette_swap` colors, from 0 to 1. Set from a
+    /// [`PaletteSwap`](palette_swap::PaletteSwap).
+    #[uniform(100)]
+    pub palette_swap_amount: f32,
+    #[uniform(101)]
+    pub palette: PixelPalette,
+    /// the colors replacing the `palette` colors with the
+    /// same index
+    #[uniform(102)]
+    pub palette_swap: PixelPalette,
+    /// Overwrites the settings above, other than the fog and
+    /// palette swap, when the style is loaded or changed
+    pub style: Option<Handle<PixelStyle>>,
+}
+
+impl Default for PixelatedExtension {
+    fn default() -> Self {
+        Self {
+            quantize_steps: 5,
+            outline_strength: 1.2,
+            normal_edge_strength: 1.,
+            depth_tolerance: 0.04,
+            dither: 0.,
+            hue_shift: 0.,
+            chroma_shift: 0.,
+            // blue
+            shadow_hue: 265.,
+            // orange
+            highlight_hue: 70.,
+            debug_view: 0,
+            outline_width: 1,
+            outline_falloff: 10.,
+            texture_pixelation: 0,
+            texel_density: 1.,
+            texture_mip_bias: 0.,
+            specular_threshold: 0.,
+            specular_color: Color::WHITE,
+            rim_width: 0.,
+            rim_color: Color::WHITE,
+            pixelated_fog: 0,
+            fog_steps: 0,
+            palette_swap_amount: 0.,
+            palette: PixelPalette::default(),
+            palette_swap: PixelPalette::default(),
+            style: None,
+        }
+    }
+}
+
+/// Colors the quantized color is snapped to. They are stored
+/// in linear rgb and compared in OKLab, where the nearest one
+/// is picked. Snapping is off without colors.
+#[derive(
+    ShaderType, Reflect, Debug, Clone, Default, PartialEq,
+)]
+pub struct PixelPalette {
+    /// linear rgb, alpha is ignored
+    pub colors: [Vec4; MAX_PALETTE_COLORS],
+    pub count: u32,
+}
+
+impl PixelPalette {
+    /// Uses the first [`MAX_PALETTE_COLORS`] colors
+    pub fn new(colors: &[Color]) -> Self {
+        let mut palette = Self::default();
+        for (slot, color) in palette
+            .colors
+            .iter_mut()
+            .zip(colors.iter().take(MAX_PALETTE_COLORS))
+        {
+            *slot = Vec4::from_array(
+                color.as_linear_rgba_f32(),
+            );
+        }
+        palette.count =
+            colors.len().min(MAX_PALETTE_COLORS) as u32;
+        palette
+    }
 }
 
 impl MaterialExtension for PixelatedExtension {
@@ -21,6 +206,12 @@ impl MaterialExtension for PixelatedExtension {
         "pixelated.wgsl".into()
     }
 
+    // follows normal and depth maps in the normal prepass,
+    // which the normal edges are detected in
+    fn prepass_fragment_shader() -> ShaderRef {
+        "pixelated_prepass.wgsl".into()
+    }
+
     // fn deferred_fragment_shader() -> ShaderRef {
     //     "shaders/pixelated.wgsl".into()
     // }
@@ -0,0 +1,368 @@
+//! Swaps the palette of pixelated materials at runtime, for
+//! day and night, team colors or damage flashes.
+//!
+//! A [`PaletteSwap`] replaces each color of a material's
+//! palette with the color at the same index of
+//! [`PaletteSwap::colors`], after quantization. As a resource
+//! it swaps every pixelated material, as a component it only
+//! swaps the materials of the entity and its descendants,
+//! which get their own copies of the materials for that.
+//!
+//! The swap fades in and out in discrete steps:
+//!
+//! ```rust,ignore
+//! // night falls over two seconds, in four steps
+//! let mut night = PaletteSwap {
+//!     steps: 4,
+//!     ..PaletteSwap::new(&night_colors)
+//! };
+//! night.swap_in(2.);
+//! commands.insert_resource(night);
+//! ```
+//!
+//! Swaps need the materials to snap to a palette, through
+//! [`PixelatedExtension::palette`] or a style.
+use crate::{PixelPalette, PixelatedExtension};
+use bevy::{
+    pbr::ExtendedMaterial, prelude::*, utils::HashMap,
+};
+
+/// Fades [`PaletteSwap`]s and copies them into the
+/// pixelated materials
+pub struct PaletteSwapPlugin;
+
+impl Plugin for PaletteSwapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                advance_palette_swaps,
+                claim_swapped_materials,
+                apply_entity_palette_swaps,
+                apply_palette_swap,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Replaces the palette colors of pixelated materials, as a
+/// resource for all of them or as a component for the
+/// materials of an entity and its descendants.
+#[derive(Resource, Component, Debug, Clone)]
+pub struct PaletteSwap {
+    /// the color replacing the palette color with the same
+    /// index, palette colors past the end are kept
+    pub colors: Vec<Color>,
+    /// how far the palette is swapped, from 0 to 1
+    pub amount: f32,
+    /// `amount` moves toward the goal by `speed` per second
+    pub goal: f32,
+    pub speed: f32,
+    /// Discrete steps the swap fades in and out in, like a
+    /// hand-made palette ramp. 0 fades smoothly.
+    pub steps: u32,
+}
+
+impl PaletteSwap {
+    /// A swap to `colors` that isn't applied yet
+    pub fn new(colors: &[Color]) -> Self {
+        Self {
+            colors: colors.to_vec(),
+            amount: 0.,
+            goal: 0.,
+            speed: 1.,
+            steps: 0,
+        }
+    }
+
+    /// Swaps to the colors over `seconds`, 0 swaps at once
+    pub fn swap_in(&mut self, seconds: f32) {
+        self.fade_to(1., seconds);
+    }
+
+    /// Swaps back to the palette over `seconds`, 0 swaps at
+    /// once
+    pub fn swap_out(&mut self, seconds: f32) {
+        self.fade_to(0., seconds);
+    }
+
+    fn fade_to(&mut self, goal: f32, seconds: f32) {
+        self.goal = goal;
+        if seconds <= 0. {
+            self.amount = goal;
+        } else {
+            self.speed = 1. / seconds;
+        }
+    }
+
+    /// `amount`, rounded to the `steps`
+    pub fn stepped_amount(&self) -> f32 {
+        let amount = self.amount.clamp(0., 1.);
+        if self.steps == 0 {
+            return amount;
+        }
+        let steps = self.steps as f32;
+        (amount * steps).round() / steps
+    }
+
+    fn palette(&self) -> PixelPalette {
+        PixelPalette::new(&self.colors)
+    }
+}
+
+type PixelatedMaterial =
+    ExtendedMaterial<StandardMaterial, PixelatedExtension>;
+
+// The copies of the materials an entity's PaletteSwap applies
+// to, by the material they were copied from
+#[derive(Component, Default)]
+struct SwappedMaterials(
+    HashMap<AssetId<PixelatedMaterial>, SwappedMaterial>,
+);
+
+struct SwappedMaterial {
+    // put back when the PaletteSwap is removed
+    original: Handle<PixelatedMaterial>,
+    copy: Handle<PixelatedMaterial>,
+}
+
+fn advance_palette_swaps(
+    time: Res<Time>,
+    swap: Option<ResMut<PaletteSwap>>,
+    mut swaps: Query<&mut PaletteSwap>,
+) {
+    let advance = |swap: &mut PaletteSwap| {
+        let step = swap.speed * time.delta_seconds();
+        swap.amount = if swap.amount < swap.goal {
+            (swap.amount + step).min(swap.goal)
+        } else {
+            (swap.amount - step).max(swap.goal)
+        };
+    };
+    if let Some(mut swap) = swap {
+        if swap.amount != swap.goal {
+            advance(&mut swap);
+        }
+    }
+    for mut swap in &mut swaps {
+        if swap.amount != swap.goal {
+            advance(&mut swap);
+        }
+    }
+}
+
+// Gives the entities with a PaletteSwap, and their
+// descendants, their own copies of their materials. Runs
+// every frame, as scenes spawn their descendants later.
+fn claim_swapped_materials(
+    mut commands: Commands,
+    mut swaps: Query<
+        (Entity, Option<&mut SwappedMaterials>),
+        With<PaletteSwap>,
+    >,
+    children: Query<&Children>,
+    mut handles: Query<&mut Handle<PixelatedMaterial>>,
+    mut materials: ResMut<Assets<PixelatedMaterial>>,
+) {
+    for (entity, swapped) in &mut swaps {
+        let Some(mut swapped) = swapped else {
+            commands
+                .entity(entity)
+                .insert(SwappedMaterials::default());
+            continue;
+        };
+        for entity in std::iter::once(entity)
+            .chain(children.iter_descendants(entity))
+        {
+            let Ok(mut handle) = handles.get_mut(entity)
+            else {
+                continue;
+            };
+            let id = handle.id();
+            if swapped
+                .0
+                .values()
+                .any(|swapped| swapped.copy.id() == id)
+            {
+                continue;
+            }
+            let copy = match swapped.0.get(&id) {
+                Some(swapped) => swapped.copy.clone(),
+                None => {
+                    let Some(material) =
+                        materials.get(id).cloned()
+                    else {
+                        continue;
+                    };
+                    let copy = materials.add(material);
+                    swapped.0.insert(
+                        id,
+                        SwappedMaterial {
+                            original: handle.clone(),
+                            copy: copy.clone(),
+                        },
+                    );
+                    copy
+                }
+            };
+            *handle = copy;
+        }
+    }
+}
+
+// Copies each entity's PaletteSwap into its copies of the
+// materials
+fn apply_entity_palette_swaps(
+    swaps: Query<(Ref<PaletteSwap>, Ref<SwappedMaterials>)>,
+    mut materials: ResMut<Assets<PixelatedMaterial>>,
+) {
+    for (swap, swapped) in &swaps {
+        if !swap.is_changed() && !swapped.is_changed() {
+            continue;
+        }
+        let amount = swap.stepped_amount();
+        let palette = swap.palette();
+        for swapped in swapped.0.values() {
+            set_palette_swap(
+                &mut materials,
+                swapped.copy.id(),
+                amount,
+                &palette,
+            );
+        }
+    }
+}
+
+// Copies the PaletteSwap resource into every material that
+// isn't swapped by an entity, when it changes and into new
+// materials
+#[allow(clippy::too_many_arguments)]
+fn apply_palette_swap(
+    mut commands: Commands,
+    swap: Option<Res<PaletteSwap>>,
+    mut had_swap: Local<bool>,
+    mut material_events: EventReader<
+        AssetEvent<PixelatedMaterial>,
+    >,
+    mut materials: ResMut<Assets<PixelatedMaterial>>,
+    entity_swaps: Query<(Entity, &SwappedMaterials)>,
+    mut removed: RemovedComponents<PaletteSwap>,
+    children: Query<&Children>,
+    mut handles: Query<&mut Handle<PixelatedMaterial>>,
+) {
+    // entities that lost their swap get their original
+    // materials back, which follow the resource's swap
+    let released: Vec<Entity> = removed.read().collect();
+    for entity in &released {
+        if let Ok((_, swapped)) = entity_swaps.get(*entity)
+        {
+            restore_materials(
+                *entity,
+                swapped,
+                &children,
+                &mut handles,
+            );
+        }
+        if let Some(mut entity) =
+            commands.get_entity(*entity)
+        {
+            entity.remove::<SwappedMaterials>();
+        }
+    }
+    let new_materials: Vec<AssetId<PixelatedMaterial>> =
+        material_events
+            .read()
+            .filter_map(|event| match event {
+                AssetEvent::Added { id } => Some(*id),
+                _ => None,
+            })
+            .collect();
+    let swap_changed =
+        swap.as_ref().is_some_and(|swap| swap.is_changed())
+            || *had_swap != swap.is_some()
+            || !released.is_empty();
+    *had_swap = swap.is_some();
+
+    if !swap_changed && new_materials.is_empty() {
+        return;
+    }
+    let (amount, palette) = swap
+        .map(|swap| (swap.stepped_amount(), swap.palette()))
+        .unwrap_or_default();
+    // released copies are still in their SwappedMaterials
+    // until the commands are applied
+    let owned: Vec<AssetId<PixelatedMaterial>> =
+        entity_swaps
+            .iter()
+            .filter(|(entity, _)| {
+                !released.contains(entity)
+            })
+            .flat_map(|(_, swapped)| swapped.0.values())
+            .map(|swapped| swapped.copy.id())
+            .collect();
+    let ids: Vec<AssetId<PixelatedMaterial>> =
+        if swap_changed {
+            materials.ids().collect()
+        } else {
+            new_materials
+        };
+    for id in ids {
+        if owned.contains(&id) {
+            continue;
+        }
+        set_palette_swap(
+            &mut materials,
+            id,
+            amount,
+            &palette,
+        );
+    }
+}
+
+// Puts the original materials back on an entity and its
+// descendants
+fn restore_materials(
+    entity: Entity,
+    swapped: &SwappedMaterials,
+    children: &Query<&Children>,
+    handles: &mut Query<&mut Handle<PixelatedMaterial>>,
+) {
+    for entity in std::iter::once(entity)
+        .chain(children.iter_descendants(entity))
+    {
+        let Ok(mut handle) = handles.get_mut(entity) else {
+            continue;
+        };
+        if let Some(swapped) =
+            swapped.0.values().find(|swapped| {
+                swapped.copy.id() == handle.id()
+            })
+        {
+            *handle = swapped.original.clone();
+        }
+    }
+}
+
+// Writes a swap into a material, without marking unchanged
+// materials as modified
+fn set_palette_swap(
+    materials: &mut Assets<PixelatedMaterial>,
+    id: AssetId<PixelatedMaterial>,
+    amount: f32,
+    palette: &PixelPalette,
+) {
+    let unchanged =
+        materials.get(id).is_none_or(|material| {
+            material.extension.palette_swap_amount == amount
+                && material.extension.palette_swap
+                    == *palette
+        });
+    if unchanged {
+        return;
+    }
+    if let Some(material) = materials.get_mut(id) {
+        material.extension.palette_swap_amount = amount;
+        material.extension.palette_swap = palette.clone();
+    }
+}
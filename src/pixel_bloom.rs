@@ -0,0 +1,334 @@
+//! Bloom in the pixel-art style, computed in the low-res image.
+//!
+//! Bevy's `BloomSettings` blurs smoothly, and that blur is
+//! either upscaled into blocky smears on the
+//! [`PixelatedCamera`](crate::pixelating_plugin::PixelatedCamera)
+//! or ignores the pixel grid on the output camera. A
+//! [`PixelatedBloom`] on the pixelated camera instead
+//! thresholds the bright texels of the low-res image and draws
+//! halos around them out of whole texels, in a few discrete
+//! rings that are dithered into each other.
+//!
+//! It runs before tonemapping, so on cameras with `hdr: true`
+//! the threshold applies to the emissive values above 1.
+//!
+//! ```rust,ignore
+//! commands.spawn((
+//!     Camera3dBundle {
+//!         camera: Camera {
+//!             hdr: true,
+//!             ..default()
+//!         },
+//!         ..default()
+//!     },
+//!     PixelatedCamera::default(),
+//!     PixelatedBloom {
+//!         threshold: 1.,
+//!         ..default()
+//!     },
+//! ));
+//! ```
+use bevy::{
+    core_pipeline::{
+        core_3d,
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_component::{
+            ComponentUniforms, DynamicUniformIndex,
+            ExtractComponent, ExtractComponentPlugin,
+            UniformComponentPlugin,
+        },
+        render_graph::{
+            NodeRunError, RenderGraphApp,
+            RenderGraphContext, ViewNode, ViewNodeRunner,
+        },
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        view::ViewTarget,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+/// add PixelatedBloom to a
+/// [`PixelatedCamera`](crate::pixelating_plugin::PixelatedCamera)
+/// to draw quantized halos around its bright texels.
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    ExtractComponent,
+    ShaderType,
+)]
+pub struct PixelatedBloom {
+    /// luminance above which a texel glows, e.g. above 1 for
+    /// emissive materials on hdr cameras
+    pub threshold: f32,
+    /// how far the halos reach, in texels. At most 16.
+    pub radius: u32,
+    /// how much of the glowing color the brightest ring adds
+    pub intensity: f32,
+    /// Rings the halos fade out in. 0 fades smoothly.
+    pub steps: u32,
+    /// how much the rings are dithered into each other, from
+    /// 0 to 1
+    pub dither: f32,
+}
+
+impl Default for PixelatedBloom {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            radius: 4,
+            intensity: 0.6,
+            steps: 3,
+            dither: 0.5,
+        }
+    }
+}
+
+pub struct PixelBloomPlugin;
+
+impl Plugin for PixelBloomPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<PixelatedBloom>::default(),
+            UniformComponentPlugin::<PixelatedBloom>::default(),
+        ));
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .add_systems(
+                Render,
+                prepare_pixel_bloom_pipelines
+                    .in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<
+                PixelBloomNode,
+            >>(
+                core_3d::graph::NAME, PixelBloomNode::NAME
+            )
+            // before tonemapping, to see the hdr values
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::END_MAIN_PASS,
+                    PixelBloomNode::NAME,
+                    core_3d::graph::node::TONEMAPPING,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .init_resource::<PixelBloomPipeline>()
+            .init_resource::<SpecializedRenderPipelines<
+                PixelBloomPipeline,
+            >>();
+    }
+}
+
+#[derive(Default)]
+struct PixelBloomNode;
+
+impl PixelBloomNode {
+    const NAME: &'static str = "pixelated_bloom";
+}
+
+impl ViewNode for PixelBloomNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static PixelBloomPipelineId,
+        &'static DynamicUniformIndex<PixelatedBloom>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, pipeline_id, settings_index): QueryItem<
+            Self::ViewQuery,
+        >,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline =
+            world.resource::<PixelBloomPipeline>();
+        let Some(render_pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id.0)
+        else {
+            return Ok(());
+        };
+        let Some(settings) = world
+            .resource::<ComponentUniforms<PixelatedBloom>>()
+            .uniforms()
+            .binding()
+        else {
+            return Ok(());
+        };
+        let post_process = view_target.post_process_write();
+        let bind_group = render_context
+            .render_device()
+            .create_bind_group(
+                "pixelated_bloom_bind_group",
+                &pipeline.layout,
+                &BindGroupEntries::sequential((
+                    post_process.source,
+                    settings,
+                )),
+            );
+
+        let mut render_pass = render_context
+            .begin_tracked_render_pass(
+                RenderPassDescriptor {
+                    label: Some("pixelated_bloom_pass"),
+                    color_attachments: &[Some(
+                        RenderPassColorAttachment {
+                            view: post_process.destination,
+                            resolve_target: None,
+                            ops: Operations::default(),
+                        },
+                    )],
+                    depth_stencil_attachment: None,
+                },
+            );
+        render_pass.set_render_pipeline(render_pipeline);
+        render_pass.set_bind_group(
+            0,
+            &bind_group,
+            &[settings_index.index()],
+        );
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct PixelBloomPipeline {
+    layout: BindGroupLayout,
+    shader: Handle<Shader>,
+}
+
+fn pixel_bloom_layout(
+    render_device: &RenderDevice,
+) -> BindGroupLayout {
+    let entry = |binding, ty| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty,
+        count: None,
+    };
+    render_device.create_bind_group_layout(
+        &BindGroupLayoutDescriptor {
+            label: Some(
+                "pixelated_bloom_bind_group_layout",
+            ),
+            entries: &[
+                // the rendered low-res image, loaded texel by
+                // texel
+                entry(
+                    0,
+                    BindingType::Texture {
+                        sample_type:
+                            TextureSampleType::Float {
+                                filterable: false,
+                            },
+                        view_dimension:
+                            TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                ),
+                entry(
+                    1,
+                    BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(
+                            PixelatedBloom::min_size(),
+                        ),
+                    },
+                ),
+            ],
+        },
+    )
+}
+
+impl FromWorld for PixelBloomPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device =
+            world.resource::<RenderDevice>();
+        Self {
+            layout: pixel_bloom_layout(render_device),
+            shader: world
+                .resource::<AssetServer>()
+                .load("pixel_bloom.wgsl"),
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for PixelBloomPipeline {
+    type Key = TextureFormat;
+
+    fn specialize(
+        &self,
+        format: Self::Key,
+    ) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("pixelated_bloom_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: vec![],
+        }
+    }
+}
+
+#[derive(Component)]
+struct PixelBloomPipelineId(CachedRenderPipelineId);
+
+fn prepare_pixel_bloom_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    pipeline: Res<PixelBloomPipeline>,
+    mut specialized: ResMut<
+        SpecializedRenderPipelines<PixelBloomPipeline>,
+    >,
+    views: Query<
+        (Entity, &ViewTarget),
+        With<PixelatedBloom>,
+    >,
+) {
+    for (entity, view_target) in &views {
+        let id = specialized.specialize(
+            &pipeline_cache,
+            &pipeline,
+            view_target.main_texture_format(),
+        );
+        commands
+            .entity(entity)
+            .insert(PixelBloomPipelineId(id));
+    }
+}
@@ -0,0 +1,952 @@
+//! Coarser pixel grids for some objects, such as chunky
+//! background terrain behind a crisp character.
+//!
+//! Every distinct [`PixelDensity`] gets its own render layer
+//! and a [`PixelDensityCamera`], which follows the
+//! [`PixelatedCamera`] and renders the entities with that
+//! density into a smaller image. After the opaque pass of the
+//! pixelated camera, these images are upscaled with nearest
+//! neighbor sampling and depth-tested against the pixelated
+//! scene, so that objects on different grids still occlude
+//! each other.
+use crate::pixelating_plugin::{
+    FirstPassImage, PixelatedCamera, PixelatedPassLayer,
+    PIXELATED_CAMERA_ORDER,
+};
+use bevy::{
+    core_pipeline::{
+        clear_color::ClearColorConfig,
+        core_3d::{self, CORE_3D_DEPTH_FORMAT},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+        prepass::{
+            DepthPrepass, NormalPrepass,
+            ViewPrepassTextures,
+        },
+        tonemapping::{DebandDither, Tonemapping},
+    },
+    ecs::query::QueryItem,
+    hierarchy::HierarchyQueryExt,
+    prelude::*,
+    render::{
+        camera::{RenderTarget, ScalingMode},
+        extract_component::{
+            ExtractComponent, ExtractComponentPlugin,
+        },
+        render_asset::RenderAssets,
+        render_graph::{
+            NodeRunError, RenderGraphApp,
+            RenderGraphContext, ViewNode, ViewNodeRunner,
+        },
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        texture::ImageSampler,
+        view::{
+            RenderLayers, ViewDepthTexture, ViewTarget,
+        },
+        Render, RenderApp, RenderSet,
+    },
+    transform::TransformSystem,
+};
+
+/// add PixelDensity to an entity on the
+/// [`PixelatedPassLayer`] to render it, and its descendants,
+/// on a coarser pixel grid.
+///
+/// The density is relative to the [`FirstPassImage`]: at
+/// `0.5` every texel of the entity covers 2x2 texels of the
+/// pixelated image. The [`FirstPassImage`] is the finest grid
+/// the pixelated image can show, so densities above `1` are
+/// rendered like `1`.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct PixelDensity(pub f32);
+
+impl Default for PixelDensity {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// The camera rendering the entities of one [`PixelDensity`].
+/// Spawned and despawned by the plugin.
+#[derive(Component, Clone, ExtractComponent)]
+pub struct PixelDensityCamera {
+    pub density: f32,
+    image: Handle<Image>,
+    depth_image: Handle<Image>,
+}
+
+// the first render layer used for densities, after the
+// pixelated pass and output layers
+const FIRST_DENSITY_LAYER: u8 = 3;
+// densities closer than this share a camera
+const DENSITY_EPSILON: f32 = 1e-3;
+// smallest density, so that images keep a size
+const MIN_DENSITY: f32 = 0.05;
+
+pub struct PixelDensityPlugin;
+
+impl Plugin for PixelDensityPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugins(
+                (
+                    ExtractComponentPlugin::<
+                        PixelDensityCamera,
+                    >::default(),
+                    ExtractComponentPlugin::<
+                        PixelDensityLayers,
+                    >::default(),
+                ),
+            )
+            .add_systems(Update, assign_pixel_densities)
+            .add_systems(
+                PostUpdate,
+                follow_pixelated_camera.before(
+                    TransformSystem::TransformPropagate,
+                ),
+            );
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .add_systems(
+                Render,
+                prepare_pixel_density_pipelines
+                    .in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<
+                StoreDensityDepthNode,
+            >>(
+                core_3d::graph::NAME,
+                StoreDensityDepthNode::NAME,
+            )
+            .add_render_graph_node::<ViewNodeRunner<
+                CompositeDensityNode,
+            >>(
+                core_3d::graph::NAME,
+                CompositeDensityNode::NAME,
+            )
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::END_PREPASSES,
+                    StoreDensityDepthNode::NAME,
+                    core_3d::graph::node::START_MAIN_PASS,
+                ],
+            )
+            // before the transparent objects of the pixelated
+            // camera, which should blend over every grid
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::MAIN_OPAQUE_PASS,
+                    CompositeDensityNode::NAME,
+                    core_3d::graph::node::MAIN_TRANSMISSIVE_PASS,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .init_resource::<PixelDensityPipelines>()
+            .init_resource::<SpecializedRenderPipelines<
+                PixelDensityPipelines,
+            >>();
+    }
+}
+
+// The images of every density camera, composited by the
+// pixelated camera
+#[derive(Component, Clone, Default, ExtractComponent)]
+struct PixelDensityLayers(Vec<PixelDensityLayer>);
+
+#[derive(Clone)]
+struct PixelDensityLayer {
+    image: Handle<Image>,
+    depth_image: Handle<Image>,
+}
+
+fn density_image(
+    size: Extent3d,
+    format: TextureFormat,
+) -> Image {
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        sampler: ImageSampler::nearest(),
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+// Moves entities with a density onto the layer of their
+// density camera, spawning the cameras that are missing and
+// despawning the ones no longer used
+#[allow(
+    clippy::type_complexity,
+    clippy::too_many_arguments
+)]
+fn assign_pixel_densities(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    first_pass_image: Res<FirstPassImage>,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+    pixelated_cameras: Query<
+        (
+            Entity,
+            &Camera,
+            &Tonemapping,
+            &DebandDither,
+            Option<&PixelDensityLayers>,
+        ),
+        With<PixelatedCamera>,
+    >,
+    density_cameras: Query<
+        (Entity, &PixelDensityCamera, &RenderLayers),
+        With<Camera>,
+    >,
+    entities: Query<(Entity, &PixelDensity)>,
+    mut removed: RemovedComponents<PixelDensity>,
+    children: Query<&Children>,
+    mut render_layers: Query<
+        Option<&mut RenderLayers>,
+        Without<Camera>,
+    >,
+    mut out_of_layers: Local<bool>,
+) {
+    let Ok((
+        pixelated_entity,
+        pixelated_camera,
+        tonemapping,
+        deband_dither,
+        current,
+    )) = pixelated_cameras.get_single()
+    else {
+        return;
+    };
+    let Some(size) = images
+        .get(&first_pass_image.0)
+        .map(|image| image.texture_descriptor.size)
+    else {
+        return;
+    };
+
+    let mut cameras: Vec<(Entity, PixelDensityCamera, u8)> =
+        density_cameras
+            .iter()
+            .filter_map(|(entity, camera, layers)| {
+                Some((
+                    entity,
+                    camera.clone(),
+                    layers.iter().next()?,
+                ))
+            })
+            .collect();
+    let mut changed = current.map(|layers| layers.0.len())
+        != Some(cameras.len());
+    let mut used = vec![];
+    let mut assignments = vec![];
+
+    for (entity, density) in &entities {
+        let density = density.0.clamp(MIN_DENSITY, 1.);
+        let layer = if (density - 1.).abs()
+            < DENSITY_EPSILON
+        {
+            pixelated_pass_layer.0
+        } else if let Some((camera, _, layer)) =
+            cameras.iter().find(|(_, camera, _)| {
+                (camera.density - density).abs()
+                    < DENSITY_EPSILON
+            })
+        {
+            used.push(*camera);
+            RenderLayers::layer(*layer)
+        } else if let Some(layer) = (FIRST_DENSITY_LAYER
+            ..RenderLayers::TOTAL_LAYERS as u8)
+            .find(|layer| {
+                cameras
+                    .iter()
+                    .all(|(_, _, other)| other != layer)
+            })
+        {
+            let scaled = |length: u32| {
+                ((length as f32 * density).round() as u32)
+                    .max(1)
+            };
+            let density_size = Extent3d {
+                width: scaled(size.width),
+                height: scaled(size.height),
+                ..default()
+            };
+            let density_camera = PixelDensityCamera {
+                density,
+                // keeps hdr colors until the pixelated camera
+                // tonemaps them
+                image: images.add(density_image(
+                    density_size,
+                    TextureFormat::Rgba16Float,
+                )),
+                depth_image: images.add(density_image(
+                    density_size,
+                    TextureFormat::R32Float,
+                )),
+            };
+            let camera = commands
+                .spawn((
+                    Camera3dBundle {
+                        camera: Camera {
+                            // render before the pixelated camera
+                            order: PIXELATED_CAMERA_ORDER
+                                - 1
+                                - (layer
+                                    - FIRST_DENSITY_LAYER)
+                                    as isize,
+                            target: RenderTarget::Image(
+                                density_camera
+                                    .image
+                                    .clone(),
+                            ),
+                            hdr: pixelated_camera.hdr,
+                            ..default()
+                        },
+                        camera_3d: Camera3d {
+                            clear_color:
+                                ClearColorConfig::Custom(
+                                    Color::NONE,
+                                ),
+                            ..default()
+                        },
+                        tonemapping: density_tonemapping(
+                            pixelated_camera,
+                            *tonemapping,
+                        ),
+                        dither: density_deband_dither(
+                            pixelated_camera,
+                            *deband_dither,
+                        ),
+                        ..default()
+                    },
+                    DepthPrepass,
+                    NormalPrepass,
+                    density_camera.clone(),
+                    RenderLayers::layer(layer),
+                    UiCameraConfig { show_ui: false },
+                ))
+                .id();
+            cameras.push((camera, density_camera, layer));
+            used.push(camera);
+            changed = true;
+            RenderLayers::layer(layer)
+        } else {
+            if !*out_of_layers {
+                warn!(
+                    "PixelDensity: no render layer left for density {density}"
+                );
+                *out_of_layers = true;
+            }
+            pixelated_pass_layer.0
+        };
+        assignments.push((entity, layer));
+    }
+    // back onto the pixelated camera's grid
+    for entity in removed.read() {
+        assignments.push((entity, pixelated_pass_layer.0));
+    }
+
+    for (entity, layer) in assignments {
+        if render_layers.get(entity).ok().flatten()
+            != Some(&layer)
+        {
+            if let Some(mut entity) =
+                commands.get_entity(entity)
+            {
+                entity.insert(layer);
+            }
+        }
+        for descendant in children.iter_descendants(entity)
+        {
+            match render_layers.get_mut(descendant) {
+                Ok(Some(mut layers)) => {
+                    if *layers != layer {
+                        *layers = layer;
+                    }
+                }
+                // e.g. meshes spawned by a scene, which are
+                // on layer 0 without render layers
+                Ok(None) => {
+                    if let Some(mut descendant) =
+                        commands.get_entity(descendant)
+                    {
+                        descendant.insert(layer);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    cameras.retain(|(camera, ..)| {
+        let keep = used.contains(camera);
+        if !keep {
+            commands.entity(*camera).despawn();
+            changed = true;
+        }
+        keep
+    });
+    if changed {
+        commands.entity(pixelated_entity).insert(
+            PixelDensityLayers(
+                cameras
+                    .into_iter()
+                    .map(|(_, camera, _)| {
+                        PixelDensityLayer {
+                            image: camera.image,
+                            depth_image: camera.depth_image,
+                        }
+                    })
+                    .collect(),
+            ),
+        );
+    }
+}
+
+// Density cameras see the scene exactly like the pixelated
+// camera does
+#[allow(clippy::type_complexity)]
+fn follow_pixelated_camera(
+    pixelated: Query<
+        (
+            &Camera,
+            &Transform,
+            &Projection,
+            &Tonemapping,
+            &DebandDither,
+        ),
+        (
+            With<PixelatedCamera>,
+            Without<PixelDensityCamera>,
+        ),
+    >,
+    mut density_cameras: Query<
+        (
+            &mut Camera,
+            &mut Transform,
+            &mut Projection,
+            &mut Tonemapping,
+            &mut DebandDither,
+        ),
+        With<PixelDensityCamera>,
+    >,
+) {
+    let Ok((
+        pixelated_camera,
+        pixelated_transform,
+        pixelated_projection,
+        pixelated_tonemapping,
+        pixelated_deband_dither,
+    )) = pixelated.get_single()
+    else {
+        return;
+    };
+    let mut projection = pixelated_projection.clone();
+    // the density images are smaller than the low-res image,
+    // so size-relative scaling would not match
+    if let Projection::Orthographic(ortho) = &mut projection
+    {
+        ortho.scaling_mode = ScalingMode::Fixed {
+            width: ortho.area.width(),
+            height: ortho.area.height(),
+        };
+        ortho.scale = 1.;
+    }
+    let tonemapping = density_tonemapping(
+        pixelated_camera,
+        *pixelated_tonemapping,
+    );
+    let deband_dither = density_deband_dither(
+        pixelated_camera,
+        *pixelated_deband_dither,
+    );
+    for (
+        mut camera,
+        mut transform,
+        mut density_projection,
+        mut density_tonemapping,
+        mut density_deband_dither,
+    ) in &mut density_cameras
+    {
+        // rendered only on the frames of the pixelated camera's
+        // frame rate
+        if camera.is_active != pixelated_camera.is_active {
+            camera.is_active = pixelated_camera.is_active;
+        }
+        *transform = *pixelated_transform;
+        *density_projection = projection.clone();
+        if *density_tonemapping != tonemapping {
+            *density_tonemapping = tonemapping;
+        }
+        if *density_deband_dither != deband_dither {
+            *density_deband_dither = deband_dither;
+        }
+    }
+}
+
+// With hdr, the density images keep hdr colors and are
+// tonemapped once, along with the pixelated camera's image.
+// Without hdr the pixelated camera tonemaps in the material
+// shaders, and the composited density images are not
+// tonemapped again, so the density cameras tonemap the same
+// way themselves.
+fn density_tonemapping(
+    pixelated_camera: &Camera,
+    tonemapping: Tonemapping,
+) -> Tonemapping {
+    if pixelated_camera.hdr {
+        Tonemapping::None
+    } else {
+        tonemapping
+    }
+}
+
+fn density_deband_dither(
+    pixelated_camera: &Camera,
+    deband_dither: DebandDither,
+) -> DebandDither {
+    if pixelated_camera.hdr {
+        DebandDither::Disabled
+    } else {
+        deband_dither
+    }
+}
+
+// Stores the prepass depth of a density camera in its depth
+// image, the way the hybrid cameras' depth is stored
+#[derive(Default)]
+struct StoreDensityDepthNode;
+
+impl StoreDensityDepthNode {
+    const NAME: &'static str =
+        "pixelated_store_density_depth";
+}
+
+impl ViewNode for StoreDensityDepthNode {
+    type ViewQuery = (
+        &'static ViewPrepassTextures,
+        &'static PixelDensityCamera,
+        &'static PixelDensityPipelineId,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (prepass_textures, camera, pipeline_id): QueryItem<
+            Self::ViewQuery,
+        >,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipelines =
+            world.resource::<PixelDensityPipelines>();
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id.id)
+        else {
+            return Ok(());
+        };
+        let Some(prepass_depth) = &prepass_textures.depth
+        else {
+            return Ok(());
+        };
+        let Some(depth_image) = world
+            .resource::<RenderAssets<Image>>()
+            .get(&camera.depth_image)
+        else {
+            return Ok(());
+        };
+
+        let bind_group = render_context
+            .render_device()
+            .create_bind_group(
+                "pixelated_store_density_depth_bind_group",
+                pipelines.store_layout(pipeline_id.samples),
+                &BindGroupEntries::single(
+                    &prepass_depth.default_view,
+                ),
+            );
+
+        let mut render_pass = render_context
+            .begin_tracked_render_pass(
+            RenderPassDescriptor {
+                label: Some(
+                    "pixelated_store_density_depth_pass",
+                ),
+                color_attachments: &[Some(
+                    RenderPassColorAttachment {
+                        view: &depth_image.texture_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(
+                                Default::default(),
+                            ),
+                            store: true,
+                        },
+                    },
+                )],
+                depth_stencil_attachment: None,
+            },
+        );
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+// Draws the upscaled density images into the color and depth
+// of the pixelated camera, where they are in front of what it
+// rendered itself
+#[derive(Default)]
+struct CompositeDensityNode;
+
+impl CompositeDensityNode {
+    const NAME: &'static str =
+        "pixelated_composite_density";
+}
+
+impl ViewNode for CompositeDensityNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ViewDepthTexture,
+        &'static PixelDensityLayers,
+        &'static PixelDensityPipelineId,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, depth, layers, pipeline_id): QueryItem<
+            Self::ViewQuery,
+        >,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if layers.0.is_empty() {
+            return Ok(());
+        }
+        let pipelines =
+            world.resource::<PixelDensityPipelines>();
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id.id)
+        else {
+            return Ok(());
+        };
+        let images =
+            world.resource::<RenderAssets<Image>>();
+        let bind_groups: Vec<BindGroup> = layers
+            .0
+            .iter()
+            .filter_map(|layer| {
+                let image = images.get(&layer.image)?;
+                let depth_image =
+                    images.get(&layer.depth_image)?;
+                Some(
+                    render_context
+                        .render_device()
+                        .create_bind_group(
+                            "pixelated_composite_density_bind_group",
+                            &pipelines.composite_layout,
+                            &BindGroupEntries::sequential((
+                                &image.texture_view,
+                                &depth_image.texture_view,
+                            )),
+                        ),
+                )
+            })
+            .collect();
+
+        let mut render_pass = render_context
+            .begin_tracked_render_pass(
+                RenderPassDescriptor {
+                    label: Some(
+                        "pixelated_composite_density_pass",
+                    ),
+                    color_attachments: &[Some(
+                        view_target.get_color_attachment(
+                            Operations {
+                                load: LoadOp::Load,
+                                store: true,
+                            },
+                        ),
+                    )],
+                    depth_stencil_attachment: Some(
+                        RenderPassDepthStencilAttachment {
+                            view: &depth.view,
+                            depth_ops: Some(Operations {
+                                load: LoadOp::Load,
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        },
+                    ),
+                },
+            );
+        render_pass.set_render_pipeline(pipeline);
+        for bind_group in &bind_groups {
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct PixelDensityPipelines {
+    store_layout: BindGroupLayout,
+    // for prepass depth rendered with msaa
+    store_layout_multisampled: BindGroupLayout,
+    composite_layout: BindGroupLayout,
+    depth_shader: Handle<Shader>,
+    composite_shader: Handle<Shader>,
+}
+
+impl PixelDensityPipelines {
+    fn store_layout(
+        &self,
+        samples: u32,
+    ) -> &BindGroupLayout {
+        if samples > 1 {
+            &self.store_layout_multisampled
+        } else {
+            &self.store_layout
+        }
+    }
+}
+
+fn texture_entry(
+    binding: u32,
+    sample_type: TextureSampleType,
+    multisampled: bool,
+) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type,
+            view_dimension: TextureViewDimension::D2,
+            multisampled,
+        },
+        count: None,
+    }
+}
+
+impl FromWorld for PixelDensityPipelines {
+    fn from_world(world: &mut World) -> Self {
+        let render_device =
+            world.resource::<RenderDevice>();
+        let store_layout = |label, multisampled| {
+            render_device.create_bind_group_layout(
+                &BindGroupLayoutDescriptor {
+                    label: Some(label),
+                    entries: &[texture_entry(
+                        0,
+                        TextureSampleType::Depth,
+                        multisampled,
+                    )],
+                },
+            )
+        };
+        let unfilterable =
+            TextureSampleType::Float { filterable: false };
+
+        Self {
+            store_layout: store_layout(
+                "pixelated_store_density_depth_bind_group_layout",
+                false,
+            ),
+            store_layout_multisampled: store_layout(
+                "pixelated_store_density_depth_multisampled_bind_group_layout",
+                true,
+            ),
+            composite_layout: render_device
+                .create_bind_group_layout(
+                    &BindGroupLayoutDescriptor {
+                        label: Some(
+                            "pixelated_composite_density_bind_group_layout",
+                        ),
+                        entries: &[
+                            texture_entry(
+                                0,
+                                unfilterable,
+                                false,
+                            ),
+                            texture_entry(
+                                1,
+                                unfilterable,
+                                false,
+                            ),
+                        ],
+                    },
+                ),
+            depth_shader: world
+                .resource::<AssetServer>()
+                .load("pixelated_depth.wgsl"),
+            composite_shader: world
+                .resource::<AssetServer>()
+                .load("pixel_density.wgsl"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum PixelDensityPipelineKey {
+    Store { samples: u32 },
+    Composite { format: TextureFormat, samples: u32 },
+}
+
+impl SpecializedRenderPipeline for PixelDensityPipelines {
+    type Key = PixelDensityPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+    ) -> RenderPipelineDescriptor {
+        match key {
+            PixelDensityPipelineKey::Store { samples } => {
+                let mut shader_defs =
+                    vec!["STORE_DEPTH".into()];
+                if samples > 1 {
+                    shader_defs.push("MULTISAMPLED".into());
+                }
+                RenderPipelineDescriptor {
+                    label: Some(
+                        "pixelated_store_density_depth_pipeline"
+                            .into(),
+                    ),
+                    layout: vec![self
+                        .store_layout(samples)
+                        .clone()],
+                    vertex: fullscreen_shader_vertex_state(),
+                    fragment: Some(FragmentState {
+                        shader: self.depth_shader.clone(),
+                        shader_defs,
+                        entry_point: "fragment".into(),
+                        targets: vec![Some(ColorTargetState {
+                            format: TextureFormat::R32Float,
+                            blend: None,
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    push_constant_ranges: vec![],
+                }
+            }
+            PixelDensityPipelineKey::Composite {
+                format,
+                samples,
+            } => RenderPipelineDescriptor {
+                label: Some(
+                    "pixelated_composite_density_pipeline"
+                        .into(),
+                ),
+                layout: vec![self.composite_layout.clone()],
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader: self.composite_shader.clone(),
+                    shader_defs: vec![],
+                    entry_point: "fragment".into(),
+                    targets: vec![Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState::default(),
+                // reverse z, nearer is greater
+                depth_stencil: Some(DepthStencilState {
+                    format: CORE_3D_DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: CompareFunction::Greater,
+                    stencil: StencilState::default(),
+                    bias: DepthBiasState::default(),
+                }),
+                multisample: MultisampleState {
+                    count: samples,
+                    ..default()
+                },
+                push_constant_ranges: vec![],
+            },
+        }
+    }
+}
+
+#[derive(Component)]
+struct PixelDensityPipelineId {
+    id: CachedRenderPipelineId,
+    samples: u32,
+}
+
+#[allow(clippy::type_complexity)]
+fn prepare_pixel_density_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    pipelines: Res<PixelDensityPipelines>,
+    mut specialized: ResMut<
+        SpecializedRenderPipelines<PixelDensityPipelines>,
+    >,
+    msaa: Res<Msaa>,
+    density_cameras: Query<
+        Entity,
+        With<PixelDensityCamera>,
+    >,
+    pixelated_cameras: Query<
+        (Entity, &ViewTarget),
+        With<PixelDensityLayers>,
+    >,
+) {
+    let samples = msaa.samples();
+    for entity in &density_cameras {
+        let id = specialized.specialize(
+            &pipeline_cache,
+            &pipelines,
+            PixelDensityPipelineKey::Store { samples },
+        );
+        commands
+            .entity(entity)
+            .insert(PixelDensityPipelineId { id, samples });
+    }
+    for (entity, view_target) in &pixelated_cameras {
+        let id = specialized.specialize(
+            &pipeline_cache,
+            &pipelines,
+            PixelDensityPipelineKey::Composite {
+                format: view_target.main_texture_format(),
+                samples,
+            },
+        );
+        commands
+            .entity(entity)
+            .insert(PixelDensityPipelineId { id, samples });
+    }
+}
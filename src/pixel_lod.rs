@@ -0,0 +1,384 @@
+//! Bigger pixels in the distance, for perspective
+//! [`PixelatedCamera`](crate::pixelating_plugin::PixelatedCamera)s.
+//!
+//! After the low-res image is rendered, every texel further
+//! away than [`PixelSizeByDistance::start`] takes the color of
+//! the corner of a bigger block, so that the far field reads as
+//! coarser pixel art, the way a lower level of detail would.
+use bevy::{
+    core_pipeline::{
+        core_3d,
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+        prepass::ViewPrepassTextures,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_component::{
+            ComponentUniforms, DynamicUniformIndex,
+            ExtractComponent, ExtractComponentPlugin,
+            UniformComponentPlugin,
+        },
+        render_graph::{
+            NodeRunError, RenderGraphApp,
+            RenderGraphContext, ViewNode, ViewNodeRunner,
+        },
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        view::{
+            ViewTarget, ViewUniform, ViewUniformOffset,
+            ViewUniforms,
+        },
+        Render, RenderApp, RenderSet,
+    },
+};
+
+/// add PixelSizeByDistance to a perspective
+/// [`PixelatedCamera`](crate::pixelating_plugin::PixelatedCamera) to render distant parts of the scene
+/// with bigger pixels.
+///
+/// Pixels are one texel big up to `start`, and grow to
+/// `max_size` texels at `end`.
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    ExtractComponent,
+    ShaderType,
+)]
+pub struct PixelSizeByDistance {
+    /// view distance at which pixels start to grow
+    pub start: f32,
+    /// view distance at which pixels are `max_size` texels big
+    pub end: f32,
+    pub max_size: u32,
+}
+
+impl Default for PixelSizeByDistance {
+    fn default() -> Self {
+        Self {
+            start: 20.,
+            end: 80.,
+            max_size: 4,
+        }
+    }
+}
+
+pub struct PixelLodPlugin;
+
+impl Plugin for PixelLodPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<PixelSizeByDistance>::default(),
+            UniformComponentPlugin::<PixelSizeByDistance>::default(),
+        ));
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .add_systems(
+                Render,
+                prepare_pixel_lod_pipelines
+                    .in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<
+                PixelLodNode,
+            >>(
+                core_3d::graph::NAME, PixelLodNode::NAME
+            )
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::TONEMAPPING,
+                    PixelLodNode::NAME,
+                    core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .init_resource::<PixelLodPipeline>()
+            .init_resource::<SpecializedRenderPipelines<
+                PixelLodPipeline,
+            >>();
+    }
+}
+
+#[derive(Default)]
+struct PixelLodNode;
+
+impl PixelLodNode {
+    const NAME: &'static str = "pixelated_pixel_lod";
+}
+
+impl ViewNode for PixelLodNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ViewPrepassTextures,
+        &'static ViewUniformOffset,
+        &'static PixelLodPipelineId,
+        &'static DynamicUniformIndex<PixelSizeByDistance>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (
+            view_target,
+            prepass_textures,
+            view_uniform_offset,
+            pipeline_id,
+            settings_index,
+        ): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline = world.resource::<PixelLodPipeline>();
+        let Some(render_pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id.id)
+        else {
+            return Ok(());
+        };
+        let Some(prepass_depth) = &prepass_textures.depth
+        else {
+            return Ok(());
+        };
+        let (Some(view_uniforms), Some(settings)) = (
+            world.resource::<ViewUniforms>().uniforms.binding(),
+            world
+                .resource::<ComponentUniforms<PixelSizeByDistance>>()
+                .uniforms()
+                .binding(),
+        ) else {
+            return Ok(());
+        };
+        let post_process = view_target.post_process_write();
+        let bind_group = render_context
+            .render_device()
+            .create_bind_group(
+                "pixelated_pixel_lod_bind_group",
+                pipeline.layout(pipeline_id.samples),
+                &BindGroupEntries::sequential((
+                    post_process.source,
+                    &prepass_depth.default_view,
+                    view_uniforms,
+                    settings,
+                )),
+            );
+
+        let mut render_pass = render_context
+            .begin_tracked_render_pass(
+                RenderPassDescriptor {
+                    label: Some("pixelated_pixel_lod_pass"),
+                    color_attachments: &[Some(
+                        RenderPassColorAttachment {
+                            view: post_process.destination,
+                            resolve_target: None,
+                            ops: Operations::default(),
+                        },
+                    )],
+                    depth_stencil_attachment: None,
+                },
+            );
+        render_pass.set_render_pipeline(render_pipeline);
+        render_pass.set_bind_group(
+            0,
+            &bind_group,
+            &[
+                view_uniform_offset.offset,
+                settings_index.index(),
+            ],
+        );
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct PixelLodPipeline {
+    layout: BindGroupLayout,
+    // for prepass depth rendered with msaa
+    layout_multisampled: BindGroupLayout,
+    shader: Handle<Shader>,
+}
+
+impl PixelLodPipeline {
+    fn layout(&self, samples: u32) -> &BindGroupLayout {
+        if samples > 1 {
+            &self.layout_multisampled
+        } else {
+            &self.layout
+        }
+    }
+}
+
+fn pixel_lod_layout(
+    render_device: &RenderDevice,
+    label: &'static str,
+    multisampled: bool,
+) -> BindGroupLayout {
+    let entry = |binding, ty| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty,
+        count: None,
+    };
+    render_device.create_bind_group_layout(
+        &BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[
+                // the rendered low-res image
+                entry(
+                    0,
+                    BindingType::Texture {
+                        sample_type:
+                            TextureSampleType::Float {
+                                filterable: false,
+                            },
+                        view_dimension:
+                            TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                ),
+                entry(
+                    1,
+                    BindingType::Texture {
+                        sample_type:
+                            TextureSampleType::Depth,
+                        view_dimension:
+                            TextureViewDimension::D2,
+                        multisampled,
+                    },
+                ),
+                entry(
+                    2,
+                    BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(
+                            ViewUniform::min_size(),
+                        ),
+                    },
+                ),
+                entry(
+                    3,
+                    BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(
+                            PixelSizeByDistance::min_size(),
+                        ),
+                    },
+                ),
+            ],
+        },
+    )
+}
+
+impl FromWorld for PixelLodPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device =
+            world.resource::<RenderDevice>();
+        Self {
+            layout: pixel_lod_layout(
+                render_device,
+                "pixelated_pixel_lod_bind_group_layout",
+                false,
+            ),
+            layout_multisampled: pixel_lod_layout(
+                render_device,
+                "pixelated_pixel_lod_multisampled_bind_group_layout",
+                true,
+            ),
+            shader: world
+                .resource::<AssetServer>()
+                .load("pixel_lod.wgsl"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct PixelLodPipelineKey {
+    format: TextureFormat,
+    samples: u32,
+}
+
+impl SpecializedRenderPipeline for PixelLodPipeline {
+    type Key = PixelLodPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+    ) -> RenderPipelineDescriptor {
+        let mut shader_defs = vec![];
+        if key.samples > 1 {
+            shader_defs.push("MULTISAMPLED".into());
+        }
+        RenderPipelineDescriptor {
+            label: Some(
+                "pixelated_pixel_lod_pipeline".into(),
+            ),
+            layout: vec![self.layout(key.samples).clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: key.format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: vec![],
+        }
+    }
+}
+
+#[derive(Component)]
+struct PixelLodPipelineId {
+    id: CachedRenderPipelineId,
+    samples: u32,
+}
+
+fn prepare_pixel_lod_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    pipeline: Res<PixelLodPipeline>,
+    mut specialized: ResMut<
+        SpecializedRenderPipelines<PixelLodPipeline>,
+    >,
+    msaa: Res<Msaa>,
+    views: Query<
+        (Entity, &ViewTarget),
+        With<PixelSizeByDistance>,
+    >,
+) {
+    for (entity, view_target) in &views {
+        let samples = msaa.samples();
+        let id = specialized.specialize(
+            &pipeline_cache,
+            &pipeline,
+            PixelLodPipelineKey {
+                format: view_target.main_texture_format(),
+                samples,
+            },
+        );
+        commands
+            .entity(entity)
+            .insert(PixelLodPipelineId { id, samples });
+    }
+}
@@ -0,0 +1,153 @@
+//! Converts between window coordinates, texels of the low-res
+//! image and the world seen by the [`PixelatedCamera`].
+//!
+//! `Camera::viewport_to_world` on the [`PixelatedCamera`]
+//! expects coordinates in the low-res image, not in the window,
+//! so window positions such as the cursor have to go through
+//! the output camera and the quad displaying the image first.
+use crate::pixelating_plugin::{
+    MainPassDisplay, PixelatedCamera,
+    PixelatedOutputCamera, DISPLAY_QUAD_SIZE,
+};
+use bevy::{
+    ecs::system::SystemParam, prelude::*,
+    window::PrimaryWindow,
+};
+
+/// System parameter for picking in the pixelated scene.
+///
+/// ```rust,ignore
+/// fn click_to_move(
+///     pixel_viewport: PixelViewport,
+///     buttons: Res<Input<MouseButton>>,
+/// ) {
+///     if buttons.just_pressed(MouseButton::Left) {
+///         if let Some(ray) = pixel_viewport.cursor_ray() {
+///             // ...
+///         }
+///     }
+/// }
+/// ```
+#[derive(SystemParam)]
+pub struct PixelViewport<'w, 's> {
+    windows:
+        Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    output_camera: Query<
+        'w,
+        's,
+        (&'static Camera, &'static GlobalTransform),
+        With<PixelatedOutputCamera>,
+    >,
+    display: Query<
+        'w,
+        's,
+        &'static GlobalTransform,
+        With<MainPassDisplay>,
+    >,
+    pixelated_camera: Query<
+        'w,
+        's,
+        (&'static Camera, &'static GlobalTransform),
+        With<PixelatedCamera>,
+    >,
+}
+
+impl<'w, 's> PixelViewport<'w, 's> {
+    /// The size of the low-res image, in texels
+    pub fn size(&self) -> Option<UVec2> {
+        let (camera, _) =
+            self.pixelated_camera.get_single().ok()?;
+        camera.physical_target_size()
+    }
+
+    /// Converts a logical position in the window into a
+    /// position in the low-res image, with (0, 0) in the
+    /// top-left corner.
+    ///
+    /// Returns `None` if the position is outside of the
+    /// displayed image, for example in the letterboxing.
+    pub fn window_to_texel(
+        &self,
+        window_position: Vec2,
+    ) -> Option<Vec2> {
+        let (camera, camera_transform) =
+            self.output_camera.get_single().ok()?;
+        let display_transform =
+            self.display.get_single().ok()?;
+
+        let viewport_position = window_position
+            - camera
+                .logical_viewport_rect()
+                .map(|rect| rect.min)
+                .unwrap_or_default();
+        let ray = camera.viewport_to_world(
+            camera_transform,
+            viewport_position,
+        )?;
+        let distance = ray.intersect_plane(
+            display_transform.translation(),
+            display_transform.back(),
+        )?;
+        let local = display_transform
+            .affine()
+            .inverse()
+            .transform_point3(ray.get_point(distance))
+            .truncate();
+
+        // the quad's uvs start in the top-left corner
+        let uv = Vec2::new(
+            local.x / DISPLAY_QUAD_SIZE.x + 0.5,
+            0.5 - local.y / DISPLAY_QUAD_SIZE.y,
+        );
+        if uv.cmplt(Vec2::ZERO).any()
+            || uv.cmpge(Vec2::ONE).any()
+        {
+            return None;
+        }
+        Some(uv * self.size()?.as_vec2())
+    }
+
+    /// Converts a position in the low-res image into a ray
+    /// in world space, cast from the [`PixelatedCamera`].
+    pub fn texel_to_world(
+        &self,
+        texel: Vec2,
+    ) -> Option<Ray> {
+        let (camera, camera_transform) =
+            self.pixelated_camera.get_single().ok()?;
+        camera.viewport_to_world(camera_transform, texel)
+    }
+
+    /// Converts a logical position in the window into a ray
+    /// in world space, cast from the center of the low-res
+    /// texel at that position.
+    pub fn window_to_world(
+        &self,
+        window_position: Vec2,
+    ) -> Option<Ray> {
+        let texel =
+            self.window_to_texel(window_position)?;
+        self.texel_to_world(texel.floor() + 0.5)
+    }
+
+    /// The texel under the cursor in the primary window
+    pub fn cursor_texel(&self) -> Option<UVec2> {
+        let cursor = self
+            .windows
+            .get_single()
+            .ok()?
+            .cursor_position()?;
+        Some(self.window_to_texel(cursor)?.as_uvec2())
+    }
+
+    /// The world space ray under the cursor in the primary
+    /// window
+    pub fn cursor_ray(&self) -> Option<Ray> {
+        let cursor = self
+            .windows
+            .get_single()
+            .ok()?
+            .cursor_position()?;
+        self.window_to_world(cursor)
+    }
+}
@@ -1,59 +1,374 @@
-use crate::PixelatedExtension;
+//! The pixelated pipeline renders in three layers, composited
+//! in camera order:
+//!
+//! 1. the [`PixelatedCamera`] (order [`PIXELATED_CAMERA_ORDER`])
+//!    renders everything on the [`PixelatedPassLayer`] into the
+//!    low-res [`FirstPassImage`].
+//! 2. the [`PixelatedOutputCamera`] (order [`OUTPUT_CAMERA_ORDER`])
+//!    renders the quad displaying the upscaled low-res image to
+//!    the window, using the [`PixelatedOutputLayer`].
+//! 3. any number of [`PixelatedOverlayCamera`]s (order
+//!    [`OVERLAY_CAMERA_ORDER`] and up) render full resolution
+//!    content such as Bevy UI, 2d sprites or 3d cursors on top
+//!    of the upscaled image.
+//!
+//! With [`PixelatedCompositing::ColorAndDepth`] the low-res depth
+//! is upscaled as well, so that
+//! [`PixelatedHybridCamera`](crate::hybrid::PixelatedHybridCamera)s can
+//! render full resolution 3d objects that are occluded by the
+//! pixelated scene.
+//!
+//! Bevy UI is never rendered into the low-res image. It is shown
+//! on the output camera until an overlay camera is spawned, after
+//! which it is shown on the overlay cameras instead.
+use crate::{
+    debug::PixelatedDebugPlugin, fog::PixelatedFogPlugin,
+    hybrid::HybridDepthPlugin,
+    palette_swap::PaletteSwapPlugin,
+    pixel_bloom::PixelBloomPlugin,
+    pixel_density::PixelDensityPlugin,
+    pixel_lod::PixelLodPlugin,
+    readback::ImageReadbackPlugin,
+    retro::RetroPostProcessPlugin,
+    sprites::PixelatedSpritePlugin,
+    stepped_animation::SteppedAnimationPlugin,
+    style::PixelStylePlugin, PixelatedExtension,
+};
 use bevy::{
+    app::PluginGroupBuilder,
+    core_pipeline::{
+        clear_color::ClearColorConfig,
+        prepass::{DepthPrepass, NormalPrepass},
+    },
     pbr::{
         ExtendedMaterial, NotShadowCaster,
         NotShadowReceiver,
     },
     prelude::*,
     render::{
-        camera::RenderTarget,
+        camera::{CameraOutputMode, RenderTarget},
+        extract_component::ExtractComponent,
+        extract_resource::ExtractResource,
         render_resource::{
-            Extent3d, TextureDescriptor, TextureDimension,
+            BlendState, Extent3d, LoadOp,
+            TextureDescriptor, TextureDimension,
             TextureFormat, TextureUsages,
         },
         texture::ImageSampler,
         view::RenderLayers,
     },
+    time::TimeSystem,
 };
 
+/// camera order of the low-res [`PixelatedCamera`]
+pub const PIXELATED_CAMERA_ORDER: isize = -1;
+/// camera order of the [`PixelatedOutputCamera`]
+pub const OUTPUT_CAMERA_ORDER: isize = 0;
+/// camera order of the lowest [`PixelatedOverlayCamera`]
+pub const OVERLAY_CAMERA_ORDER: isize = 1;
+
 /// add PixelatedCamera to your 3d camera to
 /// use it as the source of the pixelated effect
+#[derive(Component, Clone, Default, ExtractComponent)]
+pub struct PixelatedCamera {
+    /// Renders the low-res image this many times a second,
+    /// and shows the last one in between, for a choppy look
+    /// and less GPU time. UI, overlay cameras and input keep
+    /// running every frame. `None` renders every frame.
+    pub frame_rate: Option<f32>,
+}
+
+// When the PixelatedCamera last rendered
+#[derive(Component, Default)]
+struct PixelatedFrameTimer {
+    // seconds since the last rendered frame
+    elapsed: f32,
+    // whether `frame_rate` decides if the camera is active
+    holding: bool,
+}
+
+/// The camera that displays the upscaled low-res image
+/// in the window. Spawned by the plugin.
 #[derive(Component)]
-pub struct PixelatedCamera;
+pub struct PixelatedOutputCamera;
+
+/// add PixelatedOverlayCamera to a 2d or 3d camera to render
+/// its content at full resolution on top of the pixelated
+/// image.
+///
+/// Overlay cameras are composited in ascending `order`,
+/// starting at [`OVERLAY_CAMERA_ORDER`]. Their clear color is
+/// replaced with a transparent one so that only what they
+/// render covers the pixelated image.
+#[derive(Component, Default, Clone, Copy)]
+pub struct PixelatedOverlayCamera {
+    pub order: isize,
+}
+
+/// What the output camera upscales from the low-res pass.
+#[derive(
+    Resource,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    ExtractResource,
+)]
+pub enum PixelatedCompositing {
+    /// only the color of the pixelated scene
+    #[default]
+    Color,
+    /// the color, and the depth into the `FirstPassDepthImage`
+    /// for hybrid cameras to depth-test against
+    ColorAndDepth,
+}
+
+/// Everything needed to render a pixelated scene to the
+/// window. Equivalent to [`PixelatingPlugins`], without the
+/// [`PixelatedOutputPlugin`] when headless.
+#[derive(Default)]
+pub struct PixelatingPlugin {
+    /// Only render the low-res image, without the output
+    /// camera and quad displaying it in a window. Used to
+    /// render offscreen, see [`crate::headless`].
+    pub headless: bool,
+}
 
-pub struct PixelatingPlugin;
+impl PixelatingPlugin {
+    pub fn headless() -> Self {
+        Self { headless: true }
+    }
+}
 
 impl Plugin for PixelatingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(MaterialPlugin::<
-            ExtendedMaterial<
-                StandardMaterial,
-                PixelatedExtension,
-            >,
-        > {
-            prepass_enabled: true,
-            ..default()
-        })
+        let plugins = PixelatingPlugins.build();
+        if self.headless {
+            app.add_plugins(
+                plugins.disable::<PixelatedOutputPlugin>(),
+            );
+        } else {
+            app.add_plugins(plugins);
+        }
+    }
+}
+
+/// The pixelating plugins. The output, sprite and debug
+/// plugins build on the resources and assets of the plugins
+/// listed before them, which have to be added as well:
+///
+/// - [`PixelatedMaterialPlugin`]: the pixelated material,
+///   its styles, fog and palette swaps
+/// - [`PixelatedRenderTargetPlugin`]: the low-res image the
+///   [`PixelatedCamera`] renders to
+/// - [`PixelatedOutputPlugin`]: displays the upscaled low-res
+///   image in the window, with overlay and hybrid cameras
+///   and retro post-processing. Needs the render target
+///   plugin.
+/// - [`PixelatedSpritePlugin`]: draws sprites into the
+///   low-res image. Needs the render target plugin.
+/// - [`SteppedAnimationPlugin`]: animates entities with a
+///   [`SteppedAnimation`](crate::stepped_animation::SteppedAnimation)
+///   at a reduced frame rate
+/// - [`PixelatedDebugPlugin`]: cycles through the debug
+///   views. Needs the material plugin.
+///
+/// Screenshots, recording and the demo rotators are added
+/// separately with
+/// [`PixelatedScreenshotPlugin`](crate::screenshots::PixelatedScreenshotPlugin),
+/// [`PixelatedRecorderPlugin`](crate::recorder::PixelatedRecorderPlugin)
+/// and [`RotatorPlugin`](crate::rotators::RotatorPlugin).
+pub struct PixelatingPlugins;
+
+impl PluginGroup for PixelatingPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(PixelatedMaterialPlugin)
+            .add(PixelatedRenderTargetPlugin)
+            .add(PixelatedOutputPlugin)
+            .add(PixelatedSpritePlugin)
+            .add(SteppedAnimationPlugin)
+            .add(PixelatedDebugPlugin)
+    }
+}
+
+/// Only the pixelated material, for use without the low-res
+/// image. The camera rendering it still needs a
+/// `DepthPrepass` and `NormalPrepass`.
+pub struct PixelatedMaterialPlugin;
+
+impl Plugin for PixelatedMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            MaterialPlugin::<
+                ExtendedMaterial<
+                    StandardMaterial,
+                    PixelatedExtension,
+                >,
+            > {
+                prepass_enabled: true,
+                ..default()
+            },
+            PixelStylePlugin,
+            PixelatedFogPlugin,
+            PaletteSwapPlugin,
+        ));
+    }
+}
+
+/// Creates the [`FirstPassImage`] and points any
+/// [`PixelatedCamera`] at it. Also renders distant pixels
+/// bigger on cameras with
+/// [`PixelSizeByDistance`](crate::pixel_lod::PixelSizeByDistance),
+/// pixel-art bloom on cameras with
+/// [`PixelatedBloom`](crate::pixel_bloom::PixelatedBloom),
+/// and entities with a
+/// [`PixelDensity`](crate::pixel_density::PixelDensity) on
+/// their own pixel grid.
+pub struct PixelatedRenderTargetPlugin;
+
+impl Plugin for PixelatedRenderTargetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ImageReadbackPlugin,
+            PixelLodPlugin,
+            PixelBloomPlugin,
+            PixelDensityPlugin,
+        ))
+        .init_resource::<PixelatedValidation>()
         .add_systems(Startup, setup)
+        .add_systems(
+            First,
+            step_pixelated_camera.after(TimeSystem),
+        )
         .add_systems(Update, configure_pixelated_camera);
     }
 }
 
-// for saving screenshots of image
+/// Displays the upscaled [`FirstPassImage`] in the window
+/// through the [`PixelatedOutputCamera`], and composites
+/// [`PixelatedOverlayCamera`]s and
+/// [`PixelatedHybridCamera`](crate::hybrid::PixelatedHybridCamera)s
+/// with it. A
+/// [`RetroPostProcess`](crate::retro::RetroPostProcess) on
+/// the output camera adds old screen effects.
+///
+/// Needs the [`FirstPassImage`] and layers created by the
+/// [`PixelatedRenderTargetPlugin`].
+pub struct PixelatedOutputPlugin;
+
+impl Plugin for PixelatedOutputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            HybridDepthPlugin,
+            RetroPostProcessPlugin,
+        ))
+        .init_resource::<PixelatedCompositing>()
+        // the resources inserted by setup are only there
+        // once its commands are applied
+        .add_systems(
+            Startup,
+            (apply_deferred, setup_output)
+                .chain()
+                .after(setup),
+        )
+        .add_systems(Update, configure_overlay_cameras);
+    }
+}
+
+/// What to do when a [`PixelatedCamera`] is missing a
+/// `DepthPrepass` or `NormalPrepass`, which `pixelated.wgsl`
+/// depends on.
+#[derive(
+    Resource, Default, Debug, Clone, Copy, PartialEq, Eq,
+)]
+pub enum PixelatedValidation {
+    /// insert the missing prepasses, logging a warning
+    #[default]
+    Fix,
+    /// only log a warning
+    Warn,
+    /// panic in debug builds, warn in release builds
+    Strict,
+}
+
+impl PixelatedValidation {
+    fn report(&self, problem: &str) {
+        let message = format!(
+            "PixelatedCamera: {problem}, outlines will be wrong"
+        );
+        match self {
+            PixelatedValidation::Fix => {
+                warn!("{message}, fixing it")
+            }
+            PixelatedValidation::Strict
+                if cfg!(debug_assertions) =>
+            {
+                panic!("{message}")
+            }
+            _ => warn!("{message}"),
+        }
+    }
+}
+
+/// The low-res image the [`PixelatedCamera`] renders to
 #[derive(Resource)]
-struct FirstPassImage(Handle<Image>);
+pub struct FirstPassImage(pub Handle<Image>);
+
+/// The depth of the low-res pass, stored as `R32Float`.
+/// Only written to with [`PixelatedCompositing::ColorAndDepth`].
+#[derive(Resource, Clone, ExtractResource)]
+pub struct FirstPassDepthImage(pub Handle<Image>);
 
 #[derive(Resource, Deref)]
 pub struct PixelatedPassLayer(pub RenderLayers);
 
+/// The layer used by the [`PixelatedOutputCamera`] and the quad
+/// it displays. Overlay cameras should not render this layer.
+#[derive(Resource, Deref)]
+pub struct PixelatedOutputLayer(pub RenderLayers);
+
 // Marks the main pass cube, to which the texture is applied.
 #[derive(Component)]
-struct MainPassDisplay;
+pub(crate) struct MainPassDisplay;
+
+// The size of the quad displaying the low-res image
+pub(crate) const DISPLAY_QUAD_SIZE: Vec2 =
+    Vec2::new(24., 13.5);
+
+// The area covered by the quad displaying the low-res image,
+// in logical pixels of the output camera's viewport
+pub(crate) fn display_rect(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    display_transform: &GlobalTransform,
+) -> Option<Rect> {
+    let half_size = DISPLAY_QUAD_SIZE / 2.;
+    let mut rect: Option<Rect> = None;
+    for corner in [
+        Vec2::new(-half_size.x, -half_size.y),
+        Vec2::new(half_size.x, -half_size.y),
+        Vec2::new(-half_size.x, half_size.y),
+        Vec2::new(half_size.x, half_size.y),
+    ] {
+        let point = camera.world_to_viewport(
+            camera_transform,
+            display_transform
+                .transform_point(corner.extend(0.)),
+        )?;
+        rect = Some(match rect {
+            Some(rect) => {
+                rect.union(Rect::from_corners(point, point))
+            }
+            None => Rect::from_corners(point, point),
+        });
+    }
+    rect
+}
 
 fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut images: ResMut<Assets<Image>>,
 ) {
     let size = Extent3d {
@@ -71,8 +386,10 @@ fn setup(
             format: TextureFormat::Bgra8UnormSrgb,
             mip_level_count: 1,
             sample_count: 1,
+            // COPY_SRC for reading screenshots back
             usage: TextureUsages::TEXTURE_BINDING
                 | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
                 | TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
         },
@@ -83,9 +400,29 @@ fn setup(
     // fill image.data with zeroes
     image.resize(size);
 
-    let image_handle = images.add(image);
-    commands.insert_resource(FirstPassImage(
-        image_handle.clone(),
+    commands
+        .insert_resource(FirstPassImage(images.add(image)));
+
+    // The low-res depth, upscaled for hybrid cameras
+    let mut depth_image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Float,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        sampler: ImageSampler::nearest(),
+        ..default()
+    };
+    depth_image.resize(size);
+    commands.insert_resource(FirstPassDepthImage(
+        images.add(depth_image),
     ));
 
     // This specifies the layer used for the first pass, which will be attached to the first pass camera and cube.
@@ -94,6 +431,25 @@ fn setup(
         pixelated_pass_layer,
     ));
 
+    // The output camera and quad get their own layer, so that
+    // overlay cameras using the default layer don't draw the
+    // quad a second time.
+    let output_layer = RenderLayers::layer(2);
+    commands.insert_resource(PixelatedOutputLayer(
+        output_layer,
+    ));
+}
+
+// Spawns the quad and camera displaying the low-res image
+fn setup_output(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    image: Res<FirstPassImage>,
+    output_layer: Res<PixelatedOutputLayer>,
+) {
+    let output_layer = output_layer.0;
+
     // Display the pixelated image we generated with the first camera
     // it is likely that not only the size, but the approach used here
     // should change.
@@ -104,7 +460,7 @@ fn setup(
                 Vec2::new(16., 9.) * 1.5,
             ))),
             material: materials.add(StandardMaterial {
-                base_color_texture: Some(image_handle),
+                base_color_texture: Some(image.0.clone()),
                 unlit: true,
                 ..default()
             }),
@@ -114,34 +470,164 @@ fn setup(
         NotShadowCaster,
         NotShadowReceiver,
         MainPassDisplay,
+        output_layer,
     ));
 
     // The main pass camera.
-    commands.spawn(Camera3dBundle {
-        transform: Transform::from_xyz(0.0, 0.0, 15.0)
-            .looking_at(Vec3::ZERO, Vec3::Y),
-        tonemapping: bevy::core_pipeline::tonemapping::Tonemapping::TonyMcMapface,
-        ..default()
-    });
+    commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                order: OUTPUT_CAMERA_ORDER,
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 15.0)
+                .looking_at(Vec3::ZERO, Vec3::Y),
+            tonemapping: bevy::core_pipeline::tonemapping::Tonemapping::TonyMcMapface,
+            ..default()
+        },
+        PixelatedOutputCamera,
+        output_layer,
+    ));
 }
 
 // Turns any user-supplied camera (labelled with `PixelatedCamera`)
 // into the camera that renders to the pixelated texture
+#[allow(clippy::type_complexity)]
 fn configure_pixelated_camera(
     mut commands: Commands,
     mut cameras: Query<
-        (Entity, &mut Camera),
+        (
+            Entity,
+            &mut Camera,
+            Has<DepthPrepass>,
+            Has<NormalPrepass>,
+        ),
         Added<PixelatedCamera>,
     >,
     image: Res<FirstPassImage>,
     pixelated_pass_layer: Res<PixelatedPassLayer>,
+    validation: Res<PixelatedValidation>,
 ) {
-    for (entity, mut camera) in &mut cameras {
-        camera.order = -1;
+    let fix = *validation == PixelatedValidation::Fix;
+    for (
+        entity,
+        mut camera,
+        depth_prepass,
+        normal_prepass,
+    ) in &mut cameras
+    {
+        camera.order = PIXELATED_CAMERA_ORDER;
         camera.target =
             RenderTarget::Image(image.0.clone());
+        commands.entity(entity).insert((
+            pixelated_pass_layer.0,
+            // UI is never pixelated
+            UiCameraConfig { show_ui: false },
+            PixelatedFrameTimer::default(),
+        ));
+
+        if !depth_prepass {
+            validation.report("missing DepthPrepass");
+            if fix {
+                commands
+                    .entity(entity)
+                    .insert(DepthPrepass);
+            }
+        }
+        if !normal_prepass {
+            validation.report("missing NormalPrepass");
+            if fix {
+                commands
+                    .entity(entity)
+                    .insert(NormalPrepass);
+            }
+        }
+    }
+}
+
+// Deactivates the pixelated camera between the frames of its
+// `frame_rate`. The low-res image is not cleared while the
+// camera is inactive, so it keeps showing the last frame.
+fn step_pixelated_camera(
+    time: Res<Time>,
+    mut cameras: Query<(
+        &PixelatedCamera,
+        &mut Camera,
+        &mut PixelatedFrameTimer,
+    )>,
+) {
+    for (pixelated, mut camera, mut timer) in &mut cameras {
+        let Some(frame_rate) = pixelated.frame_rate else {
+            if timer.holding {
+                camera.is_active = true;
+                timer.holding = false;
+            }
+            continue;
+        };
+        let step = 1. / frame_rate.max(f32::EPSILON);
+        timer.elapsed += time.delta_seconds();
+        // the first frame is always rendered
+        let render =
+            !timer.holding || timer.elapsed >= step;
+        if render {
+            // skip the frames a long frame missed
+            timer.elapsed %= step;
+        }
+        if camera.is_active != render {
+            camera.is_active = render;
+        }
+        timer.holding = true;
+    }
+}
+
+// Layers any camera labelled with `PixelatedOverlayCamera`
+// on top of the output camera
+#[allow(clippy::type_complexity)]
+fn configure_overlay_cameras(
+    mut commands: Commands,
+    mut cameras: Query<
+        (
+            &PixelatedOverlayCamera,
+            &mut Camera,
+            Option<&mut Camera2d>,
+            Option<&mut Camera3d>,
+        ),
+        Added<PixelatedOverlayCamera>,
+    >,
+    output_cameras: Query<
+        Entity,
+        With<PixelatedOutputCamera>,
+    >,
+) {
+    if cameras.is_empty() {
+        return;
+    }
+    for (overlay, mut camera, camera_2d, camera_3d) in
+        &mut cameras
+    {
+        camera.order = OVERLAY_CAMERA_ORDER + overlay.order;
+        // the overlay is rendered onto a transparent
+        // background and blended over what the previous
+        // cameras wrote to the window
+        camera.output_mode = CameraOutputMode::Write {
+            blend_state: Some(
+                BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            ),
+            color_attachment_load_op: LoadOp::Load,
+        };
+        let clear_color =
+            ClearColorConfig::Custom(Color::NONE);
+        if let Some(mut camera_2d) = camera_2d {
+            camera_2d.clear_color = clear_color.clone();
+        }
+        if let Some(mut camera_3d) = camera_3d {
+            camera_3d.clear_color = clear_color;
+        }
+    }
+    // UI moves from the output camera to the overlays
+    for entity in &output_cameras {
         commands
             .entity(entity)
-            .insert(pixelated_pass_layer.0);
+            .insert(UiCameraConfig { show_ui: false });
     }
 }
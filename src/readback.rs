@@ -0,0 +1,297 @@
+//! Copies images from the GPU back to the CPU, for example to
+//! save the native low-res frame instead of the upscaled window.
+//!
+//! Works the same way as Bevy's `ScreenshotManager`, but for any
+//! [`Image`] that was created with
+//! [`TextureUsages::COPY_SRC`](bevy::render::render_resource::TextureUsages::COPY_SRC).
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_graph::{
+            self, NodeRunError, RenderGraph,
+            RenderGraphContext,
+        },
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages,
+            Extent3d, ImageCopyBuffer, ImageDataLayout,
+            MapMode, TextureFormat,
+        },
+        renderer::{RenderContext, RenderDevice},
+        texture::TextureFormatPixelInfo,
+        Extract, ExtractSchedule, Render, RenderApp,
+        RenderSet,
+    },
+    tasks::AsyncComputeTaskPool,
+};
+use std::sync::{Mutex, PoisonError};
+
+pub type ReadbackFn = Box<dyn FnOnce(Image) + Send + Sync>;
+
+/// Requests copies of images from the GPU.
+///
+/// The callback is called from a task once the copy is
+/// available, usually a frame or two after the request.
+#[derive(Resource, Default)]
+pub struct ImageReadbackManager {
+    requests: Mutex<Vec<(Handle<Image>, ReadbackFn)>>,
+}
+
+impl ImageReadbackManager {
+    pub fn read_image(
+        &mut self,
+        image: Handle<Image>,
+        callback: impl FnOnce(Image) + Send + Sync + 'static,
+    ) {
+        self.requests
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((image, Box::new(callback)));
+    }
+}
+
+pub struct ImageReadbackPlugin;
+
+impl Plugin for ImageReadbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ImageReadbackManager>();
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .init_resource::<PreparedReadbacks>()
+            .add_systems(ExtractSchedule, extract_readbacks)
+            .add_systems(
+                Render,
+                (
+                    prepare_readbacks.in_set(
+                        RenderSet::PrepareResources,
+                    ),
+                    collect_readbacks
+                        .in_set(RenderSet::Cleanup),
+                ),
+            );
+
+        let mut graph =
+            render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(ReadbackNode::NAME, ReadbackNode);
+        graph.add_node_edge(
+            bevy::render::main_graph::node::CAMERA_DRIVER,
+            ReadbackNode::NAME,
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+struct ExtractedReadbacks(Vec<(Handle<Image>, ReadbackFn)>);
+
+struct PreparedReadback {
+    texture: bevy::render::render_resource::Texture,
+    size: Extent3d,
+    format: TextureFormat,
+    buffer: Buffer,
+    callback: ReadbackFn,
+}
+
+#[derive(Resource, Default)]
+struct PreparedReadbacks(Vec<PreparedReadback>);
+
+fn extract_readbacks(
+    mut commands: Commands,
+    manager: Extract<Res<ImageReadbackManager>>,
+) {
+    let requests = std::mem::take(
+        &mut *manager
+            .requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+    );
+    commands.insert_resource(ExtractedReadbacks(requests));
+}
+
+fn prepare_readbacks(
+    mut extracted: ResMut<ExtractedReadbacks>,
+    mut prepared: ResMut<PreparedReadbacks>,
+    images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+) {
+    let mut not_ready = vec![];
+    for (handle, callback) in extracted.0.drain(..) {
+        // the image may not have been uploaded yet
+        let Some(gpu_image) = images.get(&handle) else {
+            not_ready.push((handle, callback));
+            continue;
+        };
+        let size = gpu_image.texture.size();
+        let format = gpu_image.texture_format;
+        let buffer = render_device.create_buffer(
+            &BufferDescriptor {
+                label: Some("pixelated_readback_buffer"),
+                size: (padded_bytes_per_row(
+                    size.width, format,
+                ) * size.height)
+                    as u64,
+                usage: BufferUsages::MAP_READ
+                    | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        prepared.0.push(PreparedReadback {
+            texture: gpu_image.texture.clone(),
+            size,
+            format,
+            buffer,
+            callback,
+        });
+    }
+    extracted.0 = not_ready;
+}
+
+// Copies every prepared readback after all cameras rendered
+struct ReadbackNode;
+
+impl ReadbackNode {
+    const NAME: &'static str = "pixelated_readback";
+}
+
+impl render_graph::Node for ReadbackNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        for readback in
+            &world.resource::<PreparedReadbacks>().0
+        {
+            render_context
+                .command_encoder()
+                .copy_texture_to_buffer(
+                    readback.texture.as_image_copy(),
+                    ImageCopyBuffer {
+                        buffer: &readback.buffer,
+                        layout: ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: Some(
+                                padded_bytes_per_row(
+                                    readback.size.width,
+                                    readback.format,
+                                ),
+                            ),
+                            rows_per_image: None,
+                        },
+                    },
+                    readback.size,
+                );
+        }
+        Ok(())
+    }
+}
+
+// Maps the buffers copied to this frame, and hands the images
+// to their callbacks once the GPU is done with them
+fn collect_readbacks(
+    mut prepared: ResMut<PreparedReadbacks>,
+) {
+    for readback in prepared.0.drain(..) {
+        let PreparedReadback {
+            size,
+            format,
+            buffer,
+            callback,
+            ..
+        } = readback;
+        let finish = async move {
+            let (tx, rx) = async_channel::bounded(1);
+            let buffer_slice = buffer.slice(..);
+            // The polling for this map call is done every frame
+            // when the command queue is submitted.
+            buffer_slice.map_async(
+                MapMode::Read,
+                move |result| {
+                    tx.try_send(result).ok();
+                },
+            );
+            if let Err(error) = rx.recv().await.unwrap() {
+                error!("Image readback failed: {error}");
+                return;
+            }
+            let data = buffer_slice.get_mapped_range();
+            let padded_row =
+                padded_bytes_per_row(size.width, format)
+                    as usize;
+            let row =
+                size.width as usize * format.pixel_size();
+            let mut result = Vec::with_capacity(
+                row * size.height as usize,
+            );
+            for padded in data.chunks(padded_row) {
+                result.extend_from_slice(&padded[..row]);
+            }
+            drop(data);
+            buffer.unmap();
+
+            callback(Image::new(
+                size,
+                bevy::render::render_resource::TextureDimension::D2,
+                result,
+                format,
+            ));
+        };
+        AsyncComputeTaskPool::get().spawn(finish).detach();
+    }
+}
+
+fn padded_bytes_per_row(
+    width: u32,
+    format: TextureFormat,
+) -> u32 {
+    RenderDevice::align_copy_bytes_per_row(
+        width as usize * format.pixel_size(),
+    ) as u32
+}
+
+/// Scales an uncompressed image up by an integer factor,
+/// duplicating every texel into a `factor` by `factor` block.
+pub fn upscale_nearest(
+    image: &Image,
+    factor: u32,
+) -> Image {
+    let factor = factor.max(1) as usize;
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+    let pixel_size =
+        image.texture_descriptor.format.pixel_size();
+    let row = width * pixel_size;
+
+    let mut data = Vec::with_capacity(
+        image.data.len() * factor * factor,
+    );
+    for y in 0..height {
+        let mut scaled_row =
+            Vec::with_capacity(row * factor);
+        for texel in image.data[y * row..(y + 1) * row]
+            .chunks(pixel_size)
+        {
+            for _ in 0..factor {
+                scaled_row.extend_from_slice(texel);
+            }
+        }
+        for _ in 0..factor {
+            data.extend_from_slice(&scaled_row);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: (width * factor) as u32,
+            height: (height * factor) as u32,
+            depth_or_array_layers: 1,
+        },
+        image.texture_descriptor.dimension,
+        data,
+        image.texture_descriptor.format,
+    )
+}
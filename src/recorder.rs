@@ -0,0 +1,559 @@
+//! Records clips of the native low-res image, using the same
+//! GPU readback as the low-res screenshots.
+//!
+//! Frames are read back at [`RecorderSettings::fps`] and
+//! encoded on a background task once the recording is done,
+//! so recording doesn't stall the game. When a game frame
+//! takes longer than a recorded frame, its image is repeated
+//! for every recorded frame that passed, so that the clip
+//! plays back at the speed it was recorded.
+use crate::{
+    pixelating_plugin::FirstPassImage,
+    readback::{upscale_nearest, ImageReadbackManager},
+};
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use image::RgbaImage;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Records clips when [`RecorderSettings::key`] is pressed,
+/// or when the recording events are sent.
+pub struct PixelatedRecorderPlugin;
+
+impl Plugin for PixelatedRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RecorderSettings>()
+            .init_resource::<Recorder>()
+            .add_event::<StartRecording>()
+            .add_event::<StopRecording>()
+            .add_event::<SaveReplay>()
+            .add_systems(
+                Update,
+                (
+                    record_on_key,
+                    handle_recording_events,
+                    capture_frames,
+                    finish_recording,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// an animated gif
+    Gif,
+    /// an animated png
+    Apng,
+    /// one png per frame, numbered from 0
+    PngSequence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingMode {
+    /// records from [`StartRecording`] until
+    /// [`RecorderSettings::duration`] has passed or
+    /// [`StopRecording`] is sent, then saves the clip
+    Clip,
+    /// records continuously, keeping the last
+    /// [`RecorderSettings::duration`] seconds, which are saved
+    /// on [`SaveReplay`]
+    Replay,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct RecorderSettings {
+    /// In [`RecordingMode::Clip`] starts and stops the
+    /// recording, in [`RecordingMode::Replay`] starts the
+    /// recording and then saves the replay.
+    /// `None` only records on events.
+    pub key: Option<KeyCode>,
+    pub mode: RecordingMode,
+    pub format: RecordingFormat,
+    /// frames captured per second
+    pub fps: u32,
+    /// length of a clip, or of the replay buffer, in seconds
+    pub duration: f32,
+    /// integer nearest neighbor upscale of every frame
+    pub scale: u32,
+    /// Colors used for every frame of a gif. `None` uses the
+    /// colors of the first recorded frame, or a NeuQuant
+    /// reduction of them when there are more than 256.
+    /// At most 256 colors are used.
+    pub palette: Option<Vec<Color>>,
+    pub directory: PathBuf,
+    /// `{n}` is replaced by the number of the recording
+    pub filename: String,
+}
+
+impl Default for RecorderSettings {
+    fn default() -> Self {
+        Self {
+            key: Some(KeyCode::R),
+            mode: RecordingMode::Clip,
+            format: RecordingFormat::Gif,
+            fps: 12,
+            duration: 5.,
+            scale: 1,
+            palette: None,
+            directory: PathBuf::from("."),
+            filename: "recording-{n}".to_string(),
+        }
+    }
+}
+
+impl RecorderSettings {
+    pub fn path(&self, n: u32) -> PathBuf {
+        let path = self.directory.join(
+            self.filename.replace("{n}", &n.to_string()),
+        );
+        match self.format {
+            RecordingFormat::Gif => {
+                path.with_extension("gif")
+            }
+            RecordingFormat::Apng => {
+                path.with_extension("png")
+            }
+            // a directory holding the frames
+            RecordingFormat::PngSequence => path,
+        }
+    }
+
+    fn max_frames(&self) -> u32 {
+        (self.duration * self.fps as f32).ceil().max(1.)
+            as u32
+    }
+}
+
+/// Starts recording, using the [`RecorderSettings`]
+#[derive(Event, Debug, Default, Clone)]
+pub struct StartRecording;
+
+/// Stops recording. A clip is saved, a replay buffer is
+/// discarded.
+#[derive(Event, Debug, Default, Clone)]
+pub struct StopRecording;
+
+/// Saves the last [`RecorderSettings::duration`] seconds while
+/// recording in [`RecordingMode::Replay`]
+#[derive(Event, Debug, Default, Clone)]
+pub struct SaveReplay {
+    /// overrides the path built from the [`RecorderSettings`]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum RecorderState {
+    #[default]
+    Idle,
+    Recording,
+    /// waiting for the last frames to be read back
+    Finishing,
+}
+
+type Frames = Arc<Mutex<BTreeMap<u32, RgbaImage>>>;
+
+/// The state of the current recording
+#[derive(Resource, Default)]
+pub struct Recorder {
+    state: RecorderState,
+    settings: Option<RecorderSettings>,
+    timer: Timer,
+    finishing_since: f32,
+    // index of the next frame to request
+    next_frame: u32,
+    // frames arrive out of order, so they are keyed by index
+    frames: Frames,
+    counter: u32,
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.state == RecorderState::Recording
+    }
+
+    fn take_frames(&mut self) -> Vec<RgbaImage> {
+        std::mem::take(&mut *lock(&self.frames))
+            .into_values()
+            .collect()
+    }
+}
+
+fn lock(
+    frames: &Frames,
+) -> std::sync::MutexGuard<'_, BTreeMap<u32, RgbaImage>> {
+    frames.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn record_on_key(
+    input: Res<Input<KeyCode>>,
+    settings: Res<RecorderSettings>,
+    recorder: Res<Recorder>,
+    mut start: EventWriter<StartRecording>,
+    mut stop: EventWriter<StopRecording>,
+    mut save: EventWriter<SaveReplay>,
+) {
+    if !settings
+        .key
+        .is_some_and(|key| input.just_pressed(key))
+    {
+        return;
+    }
+    match (recorder.is_recording(), settings.mode) {
+        (false, _) => start.send(StartRecording),
+        (true, RecordingMode::Clip) => {
+            stop.send(StopRecording)
+        }
+        (true, RecordingMode::Replay) => {
+            save.send(SaveReplay::default())
+        }
+    }
+}
+
+fn handle_recording_events(
+    mut start: EventReader<StartRecording>,
+    mut stop: EventReader<StopRecording>,
+    mut save: EventReader<SaveReplay>,
+    settings: Res<RecorderSettings>,
+    mut recorder: ResMut<Recorder>,
+    time: Res<Time>,
+) {
+    if start.read().count() > 0
+        && recorder.state == RecorderState::Idle
+    {
+        recorder.state = RecorderState::Recording;
+        recorder.settings = Some(settings.clone());
+        // the first frame is captured right away
+        recorder.timer = Timer::from_seconds(
+            1. / settings.fps.max(1) as f32,
+            TimerMode::Repeating,
+        );
+        let duration = recorder.timer.duration();
+        recorder.timer.set_elapsed(duration);
+        recorder.next_frame = 0;
+        lock(&recorder.frames).clear();
+    }
+
+    for event in save.read() {
+        let Some(settings) = recorder.settings.clone()
+        else {
+            continue;
+        };
+        if !recorder.is_recording()
+            || settings.mode != RecordingMode::Replay
+        {
+            continue;
+        }
+        let frames: Vec<RgbaImage> = lock(&recorder.frames)
+            .values()
+            .cloned()
+            .collect();
+        let path =
+            event.path.clone().unwrap_or_else(|| {
+                settings.path(recorder.counter)
+            });
+        recorder.counter += 1;
+        save_recording(frames, path, settings);
+    }
+
+    if stop.read().count() > 0 && recorder.is_recording() {
+        let replay = recorder
+            .settings
+            .as_ref()
+            .is_some_and(|settings| {
+                settings.mode == RecordingMode::Replay
+            });
+        if replay {
+            recorder.state = RecorderState::Idle;
+            lock(&recorder.frames).clear();
+        } else {
+            recorder.state = RecorderState::Finishing;
+            recorder.finishing_since =
+                time.elapsed_seconds();
+        }
+    }
+}
+
+fn capture_frames(
+    mut recorder: ResMut<Recorder>,
+    mut readback_manager: ResMut<ImageReadbackManager>,
+    first_pass_image: Res<FirstPassImage>,
+    time: Res<Time>,
+) {
+    if !recorder.is_recording() {
+        return;
+    }
+    let Some(settings) = recorder.settings.clone() else {
+        return;
+    };
+    recorder.timer.tick(time.delta());
+    // recorded frames that passed since the last capture, all
+    // of which show this frame's image
+    let mut count =
+        recorder.timer.times_finished_this_tick();
+    if count == 0 {
+        return;
+    }
+
+    let max_frames = settings.max_frames();
+    if settings.mode == RecordingMode::Clip {
+        if recorder.next_frame >= max_frames {
+            recorder.state = RecorderState::Finishing;
+            recorder.finishing_since =
+                time.elapsed_seconds();
+            return;
+        }
+        count = count.min(max_frames - recorder.next_frame);
+    }
+
+    let first = recorder.next_frame;
+    recorder.next_frame += count;
+    let frames = recorder.frames.clone();
+    let mode = settings.mode;
+    let scale = settings.scale;
+    readback_manager.read_image(
+        first_pass_image.0.clone(),
+        move |image| {
+            let image = if scale > 1 {
+                upscale_nearest(&image, scale)
+            } else {
+                image
+            };
+            let frame = match image.try_into_dynamic() {
+                Ok(image) => image.to_rgba8(),
+                Err(error) => {
+                    error!("Cannot record frame: {error}");
+                    return;
+                }
+            };
+            let mut frames = lock(&frames);
+            for index in first..first + count {
+                frames.insert(index, frame.clone());
+            }
+            // the replay buffer only keeps the newest frames
+            if mode == RecordingMode::Replay {
+                let oldest = (first + count)
+                    .saturating_sub(max_frames);
+                *frames = frames.split_off(&oldest);
+            }
+        },
+    );
+}
+
+// Saves a clip once all of its frames arrived
+fn finish_recording(
+    mut recorder: ResMut<Recorder>,
+    time: Res<Time>,
+) {
+    if recorder.state != RecorderState::Finishing {
+        return;
+    }
+    let received = lock(&recorder.frames).len() as u32;
+    // frames whose readback failed never arrive
+    let timed_out = time.elapsed_seconds()
+        - recorder.finishing_since
+        > 1.;
+    if received < recorder.next_frame && !timed_out {
+        return;
+    }
+    if received < recorder.next_frame {
+        warn!(
+            "Saving recording with {} of {} frames",
+            received, recorder.next_frame
+        );
+    }
+
+    recorder.state = RecorderState::Idle;
+    let Some(settings) = recorder.settings.take() else {
+        return;
+    };
+    let frames = recorder.take_frames();
+    let path = settings.path(recorder.counter);
+    recorder.counter += 1;
+    save_recording(frames, path, settings);
+}
+
+fn save_recording(
+    frames: Vec<RgbaImage>,
+    path: PathBuf,
+    settings: RecorderSettings,
+) {
+    if frames.is_empty() {
+        warn!("Recording has no frames, nothing saved");
+        return;
+    }
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            let result = match settings.format {
+                RecordingFormat::Gif => encode_gif(
+                    &frames,
+                    &path,
+                    settings.fps,
+                    settings.palette.as_deref(),
+                ),
+                RecordingFormat::Apng => encode_apng(
+                    &frames,
+                    &path,
+                    settings.fps,
+                ),
+                RecordingFormat::PngSequence => {
+                    encode_png_sequence(&frames, &path)
+                }
+            };
+            match result {
+                Ok(()) => info!(
+                    "Recording saved to {}",
+                    path.display()
+                ),
+                Err(error) => {
+                    error!("Cannot save recording: {error}")
+                }
+            }
+        })
+        .detach();
+}
+
+fn create_file(
+    path: &Path,
+) -> Result<BufWriter<File>, Box<dyn Error>> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+fn encode_gif(
+    frames: &[RgbaImage],
+    path: &Path,
+    fps: u32,
+    palette: Option<&[Color]>,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = frames[0].dimensions();
+    let (width, height) =
+        (u16::try_from(width)?, u16::try_from(height)?);
+    let palette: Vec<[u8; 3]> = match palette {
+        Some(palette) => {
+            if palette.len() > 256 {
+                warn!("Gif palettes have at most 256 colors, ignoring the rest");
+            }
+            palette
+                .iter()
+                .take(256)
+                .map(|color| {
+                    let [r, g, b, _] = color.as_rgba_u8();
+                    [r, g, b]
+                })
+                .collect()
+        }
+        None => frame_palette(&frames[0]),
+    };
+    let global_palette: Vec<u8> =
+        palette.iter().flatten().copied().collect();
+
+    let mut encoder = gif::Encoder::new(
+        create_file(path)?,
+        width,
+        height,
+        &global_palette,
+    )?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    // gif delays are in hundredths of a second
+    let delay = (100. / fps.max(1) as f32).round() as u16;
+    for frame in frames {
+        let indices: Vec<u8> = frame
+            .pixels()
+            .map(|pixel| nearest_index(&palette, pixel))
+            .collect();
+        let mut frame = gif::Frame::from_indexed_pixels(
+            width, height, &indices, None,
+        );
+        frame.delay = delay;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+// The colors of a captured frame, after tonemapping, outlines
+// and post-processing. A palette-snapped scene usually has
+// few enough to keep all of them.
+fn frame_palette(frame: &RgbaImage) -> Vec<[u8; 3]> {
+    let mut colors: Vec<[u8; 3]> = vec![];
+    for pixel in frame.pixels() {
+        let [r, g, b, _] = pixel.0;
+        if !colors.contains(&[r, g, b]) {
+            colors.push([r, g, b]);
+            if colors.len() > 256 {
+                break;
+            }
+        }
+    }
+    if colors.len() <= 256 {
+        return colors;
+    }
+    color_quant::NeuQuant::new(10, 256, frame.as_raw())
+        .color_map_rgb()
+        .chunks_exact(3)
+        .map(|color| [color[0], color[1], color[2]])
+        .collect()
+}
+
+fn nearest_index(
+    palette: &[[u8; 3]],
+    pixel: &image::Rgba<u8>,
+) -> u8 {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| {
+            color
+                .iter()
+                .zip(pixel.0)
+                .map(|(a, b)| (*a as i32 - b as i32).pow(2))
+                .sum::<i32>()
+        })
+        .map_or(0, |(index, _)| index as u8)
+}
+
+fn encode_apng(
+    frames: &[RgbaImage],
+    path: &Path,
+    fps: u32,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = frames[0].dimensions();
+    let mut encoder = png::Encoder::new(
+        create_file(path)?,
+        width,
+        height,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_frame_delay(1, fps.max(1) as u16)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(frame.as_raw())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn encode_png_sequence(
+    frames: &[RgbaImage],
+    directory: &Path,
+) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(directory)?;
+    for (i, frame) in frames.iter().enumerate() {
+        frame.save_with_format(
+            directory.join(format!("{i:04}.png")),
+            image::ImageFormat::Png,
+        )?;
+    }
+    Ok(())
+}
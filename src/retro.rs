@@ -0,0 +1,408 @@
+//! Retro display effects on the upscaled pixelated image.
+//!
+//! A [`RetroPostProcess`] on the
+//! [`PixelatedOutputCamera`](crate::pixelating_plugin::PixelatedOutputCamera)
+//! runs after its tonemapping: CRT curvature, chromatic
+//! aberration, scanlines, a phosphor mask and a vignette, each
+//! turned off by setting it to 0. The pipeline is specialized
+//! on which effects are on, so disabled ones cost nothing.
+//! Overlay cameras draw on top of the effects, so UI stays
+//! crisp.
+//!
+//! ```rust,ignore
+//! fn add_crt(
+//!     mut commands: Commands,
+//!     cameras: Query<Entity, Added<PixelatedOutputCamera>>,
+//! ) {
+//!     for camera in &cameras {
+//!         commands.entity(camera).insert(RetroPostProcess {
+//!             chromatic_aberration: 0.,
+//!             ..default()
+//!         });
+//!     }
+//! }
+//! ```
+use bevy::{
+    core_pipeline::{
+        core_3d,
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_component::{
+            ComponentUniforms, DynamicUniformIndex,
+            ExtractComponent, ExtractComponentPlugin,
+            UniformComponentPlugin,
+        },
+        render_graph::{
+            NodeRunError, RenderGraphApp,
+            RenderGraphContext, ViewNode, ViewNodeRunner,
+        },
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        view::ViewTarget,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+/// add RetroPostProcess to the
+/// [`PixelatedOutputCamera`](crate::pixelating_plugin::PixelatedOutputCamera)
+/// to display the pixelated image like an old screen. Every
+/// effect is off at 0.
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    ExtractComponent,
+    ShaderType,
+)]
+pub struct RetroPostProcess {
+    /// how much the image bulges like the glass of a CRT
+    pub curvature: f32,
+    /// how far the red and blue channels are pulled apart at
+    /// the edges, in window pixels
+    pub chromatic_aberration: f32,
+    /// how much darker the gaps between scanlines are, from 0
+    /// to 1
+    pub scanlines: f32,
+    /// scanlines over the window height, e.g. the height of
+    /// the low-res image
+    pub scanline_count: f32,
+    /// how strongly the red, green and blue stripes of an
+    /// aperture grille tint the window pixels, from 0 to 1
+    pub phosphor_mask: f32,
+    /// how much the corners darken, from 0 to 1
+    pub vignette: f32,
+}
+
+impl Default for RetroPostProcess {
+    fn default() -> Self {
+        Self {
+            curvature: 0.05,
+            chromatic_aberration: 1.5,
+            scanlines: 0.3,
+            scanline_count: 288.,
+            phosphor_mask: 0.2,
+            vignette: 0.3,
+        }
+    }
+}
+
+pub struct RetroPostProcessPlugin;
+
+impl Plugin for RetroPostProcessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<RetroPostProcess>::default(),
+            UniformComponentPlugin::<RetroPostProcess>::default(),
+        ));
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .add_systems(
+                Render,
+                prepare_retro_pipelines
+                    .in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<
+                RetroPostProcessNode,
+            >>(
+                core_3d::graph::NAME,
+                RetroPostProcessNode::NAME,
+            )
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::TONEMAPPING,
+                    RetroPostProcessNode::NAME,
+                    core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp)
+        else {
+            return;
+        };
+        render_app
+            .init_resource::<RetroPipeline>()
+            .init_resource::<SpecializedRenderPipelines<
+                RetroPipeline,
+            >>();
+    }
+}
+
+#[derive(Default)]
+struct RetroPostProcessNode;
+
+impl RetroPostProcessNode {
+    const NAME: &'static str = "pixelated_retro";
+}
+
+impl ViewNode for RetroPostProcessNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static RetroPipelineId,
+        &'static DynamicUniformIndex<RetroPostProcess>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, pipeline_id, settings_index): QueryItem<
+            Self::ViewQuery,
+        >,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline = world.resource::<RetroPipeline>();
+        let Some(render_pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(pipeline_id.0)
+        else {
+            return Ok(());
+        };
+        let Some(settings) = world
+            .resource::<ComponentUniforms<RetroPostProcess>>()
+            .uniforms()
+            .binding()
+        else {
+            return Ok(());
+        };
+        let post_process = view_target.post_process_write();
+        let bind_group = render_context
+            .render_device()
+            .create_bind_group(
+                "pixelated_retro_bind_group",
+                &pipeline.layout,
+                &BindGroupEntries::sequential((
+                    post_process.source,
+                    &pipeline.sampler,
+                    settings,
+                )),
+            );
+
+        let mut render_pass = render_context
+            .begin_tracked_render_pass(
+                RenderPassDescriptor {
+                    label: Some("pixelated_retro_pass"),
+                    color_attachments: &[Some(
+                        RenderPassColorAttachment {
+                            view: post_process.destination,
+                            resolve_target: None,
+                            ops: Operations::default(),
+                        },
+                    )],
+                    depth_stencil_attachment: None,
+                },
+            );
+        render_pass.set_render_pipeline(render_pipeline);
+        render_pass.set_bind_group(
+            0,
+            &bind_group,
+            &[settings_index.index()],
+        );
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct RetroPipeline {
+    layout: BindGroupLayout,
+    // filtered, so that the curved image isn't jagged
+    sampler: Sampler,
+    shader: Handle<Shader>,
+}
+
+fn retro_layout(
+    render_device: &RenderDevice,
+) -> BindGroupLayout {
+    let entry = |binding, ty| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty,
+        count: None,
+    };
+    render_device.create_bind_group_layout(
+        &BindGroupLayoutDescriptor {
+            label: Some(
+                "pixelated_retro_bind_group_layout",
+            ),
+            entries: &[
+                // the upscaled image
+                entry(
+                    0,
+                    BindingType::Texture {
+                        sample_type:
+                            TextureSampleType::Float {
+                                filterable: true,
+                            },
+                        view_dimension:
+                            TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                ),
+                entry(
+                    1,
+                    BindingType::Sampler(
+                        SamplerBindingType::Filtering,
+                    ),
+                ),
+                entry(
+                    2,
+                    BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(
+                            RetroPostProcess::min_size(),
+                        ),
+                    },
+                ),
+            ],
+        },
+    )
+}
+
+impl FromWorld for RetroPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device =
+            world.resource::<RenderDevice>();
+        Self {
+            layout: retro_layout(render_device),
+            sampler: render_device.create_sampler(
+                &SamplerDescriptor {
+                    mag_filter: FilterMode::Linear,
+                    min_filter: FilterMode::Linear,
+                    ..default()
+                },
+            ),
+            shader: world
+                .resource::<AssetServer>()
+                .load("retro.wgsl"),
+        }
+    }
+}
+
+/// the view's texture format and which effects are on, so
+/// that the shader skips the ones set to 0
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct RetroPipelineKey {
+    format: TextureFormat,
+    curvature: bool,
+    chromatic_aberration: bool,
+    scanlines: bool,
+    phosphor_mask: bool,
+    vignette: bool,
+}
+
+impl RetroPipelineKey {
+    fn new(
+        format: TextureFormat,
+        settings: &RetroPostProcess,
+    ) -> Self {
+        Self {
+            format,
+            curvature: settings.curvature != 0.,
+            chromatic_aberration: settings
+                .chromatic_aberration
+                != 0.,
+            scanlines: settings.scanlines != 0.
+                && settings.scanline_count != 0.,
+            phosphor_mask: settings.phosphor_mask != 0.,
+            vignette: settings.vignette != 0.,
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.curvature
+            || self.chromatic_aberration
+            || self.scanlines
+            || self.phosphor_mask
+            || self.vignette
+    }
+
+    fn shader_defs(&self) -> Vec<ShaderDefVal> {
+        [
+            (self.curvature, "CURVATURE"),
+            (
+                self.chromatic_aberration,
+                "CHROMATIC_ABERRATION",
+            ),
+            (self.scanlines, "SCANLINES"),
+            (self.phosphor_mask, "PHOSPHOR_MASK"),
+            (self.vignette, "VIGNETTE"),
+        ]
+        .into_iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, def)| def.into())
+        .collect()
+    }
+}
+
+impl SpecializedRenderPipeline for RetroPipeline {
+    type Key = RetroPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+    ) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("pixelated_retro_pipeline".into()),
+            layout: vec![self.layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: key.shader_defs(),
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: key.format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: vec![],
+        }
+    }
+}
+
+#[derive(Component)]
+struct RetroPipelineId(CachedRenderPipelineId);
+
+fn prepare_retro_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    pipeline: Res<RetroPipeline>,
+    mut specialized: ResMut<
+        SpecializedRenderPipelines<RetroPipeline>,
+    >,
+    views: Query<(Entity, &ViewTarget, &RetroPostProcess)>,
+) {
+    for (entity, view_target, settings) in &views {
+        let key = RetroPipelineKey::new(
+            view_target.main_texture_format(),
+            settings,
+        );
+        // without a pipeline the node doesn't run, so nothing
+        // is copied when every effect is off
+        if !key.any() {
+            continue;
+        }
+        let id = specialized.specialize(
+            &pipeline_cache,
+            &pipeline,
+            key,
+        );
+        commands.entity(entity).insert(RetroPipelineId(id));
+    }
+}
@@ -2,6 +2,22 @@ use std::f32::consts::FRAC_PI_4;
 
 use bevy::prelude::*;
 
+/// Adds the rotator systems used by the examples
+pub struct RotatorPlugin;
+
+impl Plugin for RotatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                circle_rotator_system,
+                light_rotator_system,
+                rotator_system,
+            ),
+        );
+    }
+}
+
 #[derive(Component)]
 pub struct Rotate;
 
@@ -1,52 +1,241 @@
+use crate::{
+    pixelating_plugin::FirstPassImage,
+    readback::{upscale_nearest, ImageReadbackManager},
+};
 use bevy::{
     prelude::*,
     render::view::screenshot::ScreenshotManager,
     window::PrimaryWindow,
 };
+use std::path::PathBuf;
+
+/// Saves screenshots when [`ScreenshotSettings::key`] is
+/// pressed, or when a [`TakeScreenshot`] event is sent.
+pub struct PixelatedScreenshotPlugin;
+
+impl Plugin for PixelatedScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenshotSettings>()
+            .add_event::<TakeScreenshot>()
+            .add_systems(
+                Update,
+                (screenshot_on_key, take_screenshots)
+                    .chain(),
+            );
+    }
+}
+
+/// What a screenshot captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotSource {
+    /// the upscaled window, including overlays
+    Window,
+    /// the native low-res image, one pixel per texel
+    LowRes,
+    /// the low-res image, scaled up by an integer factor
+    /// using nearest neighbor sampling
+    Upscaled(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    Png,
+    Jpeg,
+    Bmp,
+}
+
+impl ScreenshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Jpeg => "jpg",
+            ScreenshotFormat::Bmp => "bmp",
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct ScreenshotSettings {
+    /// `None` only takes screenshots on [`TakeScreenshot`]
+    pub key: Option<KeyCode>,
+    pub source: ScreenshotSource,
+    pub directory: PathBuf,
+    /// `{n}` is replaced by the number of the screenshot
+    pub filename: String,
+    pub format: ScreenshotFormat,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            key: Some(KeyCode::Space),
+            source: ScreenshotSource::Window,
+            directory: PathBuf::from("."),
+            filename: "screenshot-{n}".to_string(),
+            format: ScreenshotFormat::Png,
+        }
+    }
+}
+
+impl ScreenshotSettings {
+    pub fn path(&self, n: u32) -> PathBuf {
+        // appended instead of `with_extension`, which would
+        // replace anything after a dot in the filename
+        self.directory.join(format!(
+            "{}.{}",
+            self.filename.replace("{n}", &n.to_string()),
+            self.format.extension()
+        ))
+    }
+}
+
+/// Takes a screenshot using the [`ScreenshotSettings`]
+#[derive(Event, Debug, Default, Clone)]
+pub struct TakeScreenshot {
+    /// overrides [`ScreenshotSettings::source`]
+    pub source: Option<ScreenshotSource>,
+    /// overrides the path built from the [`ScreenshotSettings`]
+    pub path: Option<PathBuf>,
+}
+
+pub fn screenshot_on_key(
+    input: Res<Input<KeyCode>>,
+    settings: Res<ScreenshotSettings>,
+    mut screenshots: EventWriter<TakeScreenshot>,
+) {
+    if settings
+        .key
+        .is_some_and(|key| input.just_pressed(key))
+    {
+        screenshots.send(TakeScreenshot::default());
+    }
+}
 
+/// Saves the window to `./screenshot-{n}.png` when space is
+/// pressed
+#[deprecated(
+    note = "add `PixelatedScreenshotPlugin` instead, which is configured through `ScreenshotSettings`"
+)]
 pub fn screenshot_on_spacebar(
     input: Res<Input<KeyCode>>,
     main_window: Query<Entity, With<PrimaryWindow>>,
     mut screenshot_manager: ResMut<ScreenshotManager>,
     mut counter: Local<u32>,
-    // first_pass_image: Res<FirstPassImage>,
-    // images: Res<Assets<Image>>,
 ) {
     if input.just_pressed(KeyCode::Space) {
         let path = format!("./screenshot-{}.png", *counter);
         *counter += 1;
-        screenshot_manager
+        if let Err(error) = screenshot_manager
             .save_screenshot_to_disk(
                 main_window.single(),
                 path,
             )
-            .unwrap();
-
-        // dbg!(images.iter().count());
-        // for (i, (id, image)) in images.iter().enumerate() {
-        //     let path = format!(
-        //         "./screenshot-all-{}-{i}.png",
-        //         *counter
-        //     );
-        //     match image.texture_descriptor.format {
-        //         TextureFormat::R8Unorm
-        //         | TextureFormat::Rg8Unorm
-        //         | TextureFormat::Rgba8UnormSrgb
-        //         | TextureFormat::Bgra8UnormSrgb => {
-        //             dbg!(id);
-        //             // let i =
-        //             // images.get(&first_pass_image.0).unwrap();
-        //             // println!("{:?}", &i.data);
-        //             dbg!(image.size());
-        //             let dyn_image = image
-        //                 .clone()
-        //                 .try_into_dynamic()
-        //                 .unwrap();
-        //             // dbg!(dyn_image.as_bytes());
-        //             dyn_image.save(path).unwrap();
-        //         }
-        //         _ => {}
-        //     }
-        // }
+        {
+            warn!("{error}");
+        }
+    }
+}
+
+pub fn take_screenshots(
+    mut screenshots: EventReader<TakeScreenshot>,
+    settings: Res<ScreenshotSettings>,
+    main_window: Query<Entity, With<PrimaryWindow>>,
+    mut screenshot_manager: ResMut<ScreenshotManager>,
+    mut readback_manager: ResMut<ImageReadbackManager>,
+    first_pass_image: Res<FirstPassImage>,
+    mut counter: Local<u32>,
+) {
+    for screenshot in screenshots.read() {
+        let path = screenshot
+            .path
+            .clone()
+            .unwrap_or_else(|| settings.path(*counter));
+        *counter += 1;
+        if let Some(directory) = path.parent() {
+            if let Err(error) =
+                std::fs::create_dir_all(directory)
+            {
+                error!("Cannot create screenshot directory: {error}");
+                continue;
+            }
+        }
+        let format = settings.format;
+
+        match screenshot.source.unwrap_or(settings.source) {
+            ScreenshotSource::Window => {
+                let Ok(window) = main_window.get_single()
+                else {
+                    continue;
+                };
+                if let Err(error) = screenshot_manager
+                    .take_screenshot(window, move |image| {
+                        save_image(image, path, format)
+                    })
+                {
+                    warn!("{error}");
+                }
+            }
+            ScreenshotSource::LowRes => {
+                readback_manager.read_image(
+                    first_pass_image.0.clone(),
+                    move |image| {
+                        save_image(image, path, format)
+                    },
+                );
+            }
+            ScreenshotSource::Upscaled(factor) => {
+                readback_manager.read_image(
+                    first_pass_image.0.clone(),
+                    move |image| {
+                        save_image(
+                            upscale_nearest(&image, factor),
+                            path,
+                            format,
+                        )
+                    },
+                );
+            }
+        }
+    }
+}
+
+fn save_image(
+    image: Image,
+    path: PathBuf,
+    format: ScreenshotFormat,
+) {
+    let image = match image.try_into_dynamic() {
+        Ok(image) => image,
+        Err(error) => {
+            error!("Cannot save screenshot: {error}");
+            return;
+        }
+    };
+    let result = match format {
+        ScreenshotFormat::Png => image.save_with_format(
+            &path,
+            image::ImageFormat::Png,
+        ),
+        // jpeg and bmp have no alpha channel
+        ScreenshotFormat::Jpeg => {
+            image.to_rgb8().save_with_format(
+                &path,
+                image::ImageFormat::Jpeg,
+            )
+        }
+        ScreenshotFormat::Bmp => {
+            image.to_rgb8().save_with_format(
+                &path,
+                image::ImageFormat::Bmp,
+            )
+        }
+    };
+    match result {
+        Ok(()) => {
+            info!("Screenshot saved to {}", path.display())
+        }
+        Err(error) => {
+            error!("Cannot save screenshot: {error}")
+        }
     }
 }
@@ -0,0 +1,451 @@
+//! Hand-drawn 2d sprites in the pixelated 3d scene.
+//!
+//! Entities with a [`PixelatedSprite`] and either a `Sprite`
+//! and an image, or a `TextureAtlasSprite` and an atlas, are
+//! drawn by the [`PixelatedCamera`] as a textured quad instead
+//! of by 2d cameras. The quad is scaled so that every texel of
+//! the sprite covers one pixel of the low-res image, and is
+//! depth tested against the rest of the pixelated scene.
+//!
+//! ```rust,ignore
+//! commands.spawn((
+//!     SpriteBundle {
+//!         texture: asset_server.load("character.png"),
+//!         transform: Transform::from_xyz(0., 1., 0.),
+//!         ..default()
+//!     },
+//!     PixelatedSprite::default(),
+//! ));
+//! ```
+use crate::pixelating_plugin::{
+    PixelatedCamera, PixelatedPassLayer,
+};
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        view::{NoFrustumCulling, VisibilitySystems},
+    },
+    transform::TransformSystem,
+};
+
+/// Draws the quads of [`PixelatedSprite`]s. Needs the
+/// [`PixelatedPassLayer`] of the
+/// [`PixelatedRenderTargetPlugin`](crate::pixelating_plugin::PixelatedRenderTargetPlugin).
+pub struct PixelatedSpritePlugin;
+
+impl Plugin for PixelatedSpritePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (spawn_sprite_quads, update_sprite_quads)
+                .chain(),
+        )
+        .add_systems(
+            PostUpdate,
+            place_sprite_quads
+                .after(TransformSystem::TransformPropagate)
+                .before(VisibilitySystems::CheckVisibility),
+        );
+    }
+}
+
+/// add PixelatedSprite to a `SpriteBundle` or
+/// `SpriteSheetBundle` to draw it into the low-res image at
+/// one texel per low-res pixel.
+///
+/// The sprite is moved to the [`PixelatedPassLayer`], so 2d
+/// overlay cameras no longer draw it. Sprites are alpha
+/// masked: texels are either drawn or not.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct PixelatedSprite {
+    pub orientation: SpriteOrientation,
+}
+
+/// Which way the quad of a [`PixelatedSprite`] faces
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpriteOrientation {
+    /// always faces the camera, with the sprite's pixels
+    /// snapped to the low-res pixel grid
+    #[default]
+    Billboard,
+    /// faces the camera but stays upright, for characters
+    /// standing on the ground
+    Upright,
+    /// keeps the rotation of the entity, for tiles and decals
+    /// laid out in the world
+    Fixed,
+}
+
+// The quad drawing a sprite, a child of the sprite entity
+#[derive(Component)]
+struct PixelatedSpriteQuad {
+    quad: Entity,
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    // the lower left corner of the quad, in texels from the
+    // sprite's origin
+    min: Vec2,
+    // false until the image or atlas is loaded
+    ready: bool,
+}
+
+fn spawn_sprite_quads(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    sprites: Query<
+        Entity,
+        (
+            With<PixelatedSprite>,
+            Without<PixelatedSpriteQuad>,
+        ),
+    >,
+    pixelated_pass_layer: Res<PixelatedPassLayer>,
+) {
+    for entity in &sprites {
+        let mesh = meshes.add(sprite_quad(
+            Vec2::ZERO,
+            Vec2::ZERO,
+            Rect::default(),
+            Vec2::ONE,
+            false,
+            false,
+        ));
+        let material = materials.add(StandardMaterial {
+            unlit: true,
+            alpha_mode: AlphaMode::Mask(0.5),
+            double_sided: true,
+            cull_mode: None,
+            ..default()
+        });
+        let quad = commands
+            .spawn((
+                MaterialMeshBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    ..default()
+                },
+                NotShadowCaster,
+                NotShadowReceiver,
+                // the bounds would be computed from the empty
+                // quad
+                NoFrustumCulling,
+                pixelated_pass_layer.0,
+            ))
+            .id();
+        commands
+            .entity(entity)
+            .insert((
+                PixelatedSpriteQuad {
+                    quad,
+                    mesh,
+                    material,
+                    min: Vec2::ZERO,
+                    ready: false,
+                },
+                // only the quad is drawn, by the pixelated
+                // camera
+                pixelated_pass_layer.0,
+            ))
+            .add_child(quad);
+    }
+}
+
+// Matches the quads to their sprite's size, texture rect,
+// anchor, flip and color
+#[allow(clippy::type_complexity)]
+fn update_sprite_quads(
+    mut sprites: Query<(
+        &mut PixelatedSpriteQuad,
+        Option<Ref<Sprite>>,
+        Option<Ref<Handle<Image>>>,
+        Option<Ref<TextureAtlasSprite>>,
+        Option<Ref<Handle<TextureAtlas>>>,
+    )>,
+    images: Res<Assets<Image>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (mut quad, sprite, image, atlas_sprite, atlas) in
+        &mut sprites
+    {
+        let changed = sprite
+            .as_ref()
+            .is_some_and(|sprite| sprite.is_changed())
+            || image
+                .as_ref()
+                .is_some_and(|image| image.is_changed())
+            || atlas_sprite
+                .as_ref()
+                .is_some_and(|sprite| sprite.is_changed())
+            || atlas
+                .as_ref()
+                .is_some_and(|atlas| atlas.is_changed());
+        if quad.ready && !changed {
+            continue;
+        }
+
+        // texture, its size and the texel rect drawn, then
+        // the sprite's settings
+        let (
+            texture,
+            texture_size,
+            rect,
+            custom_size,
+            anchor,
+            flip_x,
+            flip_y,
+            color,
+        ) = match (sprite, image, atlas_sprite, atlas) {
+            (Some(sprite), Some(image), _, _) => {
+                let Some(size) =
+                    images.get(&*image).map(|image| {
+                        let size =
+                            image.texture_descriptor.size;
+                        Vec2::new(
+                            size.width as f32,
+                            size.height as f32,
+                        )
+                    })
+                else {
+                    continue;
+                };
+                (
+                    image.clone(),
+                    size,
+                    sprite.rect.unwrap_or(
+                        Rect::from_corners(
+                            Vec2::ZERO,
+                            size,
+                        ),
+                    ),
+                    sprite.custom_size,
+                    sprite.anchor.as_vec(),
+                    sprite.flip_x,
+                    sprite.flip_y,
+                    sprite.color,
+                )
+            }
+            (_, _, Some(sprite), Some(atlas)) => {
+                let Some(atlas) = atlases.get(&*atlas)
+                else {
+                    continue;
+                };
+                let Some(rect) =
+                    atlas.textures.get(sprite.index)
+                else {
+                    continue;
+                };
+                (
+                    atlas.texture.clone(),
+                    atlas.size,
+                    *rect,
+                    sprite.custom_size,
+                    sprite.anchor.as_vec(),
+                    sprite.flip_x,
+                    sprite.flip_y,
+                    sprite.color,
+                )
+            }
+            _ => continue,
+        };
+
+        let size = custom_size.unwrap_or(rect.size());
+        if let Some(mesh) = meshes.get_mut(&quad.mesh) {
+            *mesh = sprite_quad(
+                size,
+                anchor,
+                rect,
+                texture_size,
+                flip_x,
+                flip_y,
+            );
+        }
+        if let Some(material) =
+            materials.get_mut(&quad.material)
+        {
+            material.base_color = color;
+            material.base_color_texture = Some(texture);
+        }
+        quad.min = (Vec2::splat(-0.5) - anchor) * size;
+        quad.ready = true;
+    }
+}
+
+// A quad in texels, with its uvs covering `rect` of a texture
+// of `texture_size`
+fn sprite_quad(
+    size: Vec2,
+    anchor: Vec2,
+    rect: Rect,
+    texture_size: Vec2,
+    flip_x: bool,
+    flip_y: bool,
+) -> Mesh {
+    let min = (Vec2::splat(-0.5) - anchor) * size;
+    let max = (Vec2::splat(0.5) - anchor) * size;
+    let mut uv_min = rect.min / texture_size;
+    let mut uv_max = rect.max / texture_size;
+    if flip_x {
+        std::mem::swap(&mut uv_min.x, &mut uv_max.x);
+    }
+    if flip_y {
+        std::mem::swap(&mut uv_min.y, &mut uv_max.y);
+    }
+
+    let mut mesh =
+        Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        vec![
+            [min.x, min.y, 0.],
+            [max.x, min.y, 0.],
+            [max.x, max.y, 0.],
+            [min.x, max.y, 0.],
+        ],
+    );
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[0., 0., 1.]; 4],
+    );
+    // image space, y down
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_UV_0,
+        vec![
+            [uv_min.x, uv_max.y],
+            [uv_max.x, uv_max.y],
+            [uv_max.x, uv_min.y],
+            [uv_min.x, uv_min.y],
+        ],
+    );
+    mesh.set_indices(Some(Indices::U32(vec![
+        0, 1, 2, 0, 2, 3,
+    ])));
+    mesh
+}
+
+// World units covered by one low-res pixel at a distance from
+// the camera
+fn world_per_texel(
+    projection: &Projection,
+    image_height: f32,
+    distance: f32,
+) -> f32 {
+    match projection {
+        Projection::Orthographic(ortho) => {
+            ortho.area.height() / image_height
+        }
+        Projection::Perspective(perspective) => {
+            2. * distance * (perspective.fov / 2.).tan()
+                / image_height
+        }
+    }
+}
+
+// Scales, turns and snaps the quads after the transforms are
+// propagated, so that they use this frame's camera. The global
+// transforms of the quads are written directly.
+fn place_sprite_quads(
+    cameras: Query<
+        (&Camera, &GlobalTransform, &Projection),
+        With<PixelatedCamera>,
+    >,
+    sprites: Query<(
+        &PixelatedSprite,
+        &PixelatedSpriteQuad,
+        &GlobalTransform,
+    )>,
+    mut quads: Query<
+        (&mut Transform, &mut GlobalTransform),
+        Without<PixelatedSpriteQuad>,
+    >,
+) {
+    let Ok((camera, camera_transform, projection)) =
+        cameras.get_single()
+    else {
+        return;
+    };
+    let Some(image_size) = camera.logical_viewport_size()
+    else {
+        return;
+    };
+    let forward = camera_transform.forward();
+    let right = camera_transform.right();
+    let up = camera_transform.up();
+
+    for (sprite, quad, sprite_transform) in &sprites {
+        let Ok((mut transform, mut global_transform)) =
+            quads.get_mut(quad.quad)
+        else {
+            continue;
+        };
+        let (
+            sprite_scale,
+            sprite_rotation,
+            mut translation,
+        ) = sprite_transform
+            .to_scale_rotation_translation();
+        let distance = (translation
+            - camera_transform.translation())
+        .dot(forward);
+        let scale = world_per_texel(
+            projection,
+            image_size.y,
+            distance,
+        );
+
+        let rotation = match sprite.orientation {
+            SpriteOrientation::Billboard => {
+                // move the lower left corner onto a pixel
+                // corner, so that every texel covers exactly
+                // one pixel
+                let min =
+                    quad.min * sprite_scale.truncate();
+                let corner = translation
+                    + (right * min.x + up * min.y) * scale;
+                if let Some(viewport) = camera
+                    .world_to_viewport(
+                        camera_transform,
+                        corner,
+                    )
+                {
+                    let offset =
+                        viewport.round() - viewport;
+                    // viewport y points down
+                    translation += (right * offset.x
+                        - up * offset.y)
+                        * scale;
+                }
+                camera_transform
+                    .compute_transform()
+                    .rotation
+            }
+            SpriteOrientation::Upright => {
+                let back =
+                    Vec3::new(-forward.x, 0., -forward.z)
+                        .normalize_or_zero();
+                if back == Vec3::ZERO {
+                    sprite_rotation
+                } else {
+                    Quat::from_rotation_arc(Vec3::Z, back)
+                }
+            }
+            SpriteOrientation::Fixed => sprite_rotation,
+        };
+
+        let target = GlobalTransform::from(Transform {
+            translation,
+            rotation,
+            scale: (sprite_scale.truncate() * scale)
+                .extend(1.),
+        });
+        *transform = Transform::from_matrix(
+            Mat4::from(sprite_transform.affine().inverse())
+                * target.compute_matrix(),
+        );
+        *global_transform = target;
+    }
+}
@@ -0,0 +1,147 @@
+//! Animation "on twos" for pixelated meshes.
+//!
+//! Entities with a [`SteppedAnimation`] are shown at the pose
+//! they had at the last step, and only move on to their current
+//! pose `fps` times a second. Their transforms and the
+//! transforms of their descendants, e.g. the joints an
+//! `AnimationPlayer` moves, keep updating every frame: the held
+//! pose is only swapped in for rendering, after the animations
+//! are applied, and swapped back out at the start of the next
+//! frame. Systems like
+//! [`rotator_system`](crate::rotators::rotator_system) keep
+//! working on the live transforms.
+//!
+//! ```rust,ignore
+//! commands.spawn((
+//!     SceneBundle {
+//!         scene: asset_server.load("character.glb#Scene0"),
+//!         ..default()
+//!     },
+//!     SteppedAnimation { fps: 12. },
+//! ));
+//! ```
+use bevy::{
+    animation::animation_player, prelude::*,
+    transform::TransformSystem,
+};
+
+/// Holds the poses of [`SteppedAnimation`]s between steps
+pub struct SteppedAnimationPlugin;
+
+impl Plugin for SteppedAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(First, restore_live_transforms)
+            .add_systems(
+                PostUpdate,
+                hold_stepped_transforms
+                    .after(animation_player)
+                    .before(
+                        TransformSystem::TransformPropagate,
+                    ),
+            );
+    }
+}
+
+/// add SteppedAnimation to an entity to show its motion, and
+/// the motion of its descendants, at `fps` poses a second,
+/// independent of the frame rate.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SteppedAnimation {
+    pub fps: f32,
+}
+
+impl Default for SteppedAnimation {
+    fn default() -> Self {
+        Self { fps: 12. }
+    }
+}
+
+// The poses of an entity with a SteppedAnimation and of its
+// descendants
+#[derive(Component, Default)]
+struct SteppedPoses {
+    // seconds since the last step
+    elapsed: f32,
+    // the pose shown until the next step
+    held: Vec<(Entity, Transform)>,
+    // the transforms as updated this frame, put back before the
+    // next one
+    live: Vec<(Entity, Transform)>,
+}
+
+fn hold_stepped_transforms(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut stepped: Query<(
+        Entity,
+        &SteppedAnimation,
+        Option<&mut SteppedPoses>,
+    )>,
+    children: Query<&Children>,
+    mut transforms: Query<&mut Transform>,
+) {
+    for (entity, animation, poses) in &mut stepped {
+        let Some(mut poses) = poses else {
+            // held from the next frame on
+            commands
+                .entity(entity)
+                .insert(SteppedPoses::default());
+            continue;
+        };
+
+        let entities = std::iter::once(entity)
+            .chain(children.iter_descendants(entity));
+        poses.live = entities
+            .filter_map(|entity| {
+                transforms
+                    .get(entity)
+                    .ok()
+                    .map(|transform| (entity, *transform))
+            })
+            .collect();
+
+        let step = 1. / animation.fps.max(f32::EPSILON);
+        poses.elapsed += time.delta_seconds();
+        if poses.held.is_empty() || poses.elapsed >= step {
+            // skip the steps a long frame missed
+            poses.elapsed %= step;
+            poses.held = poses.live.clone();
+        }
+
+        for (entity, held) in &poses.held {
+            if let Ok(mut transform) =
+                transforms.get_mut(*entity)
+            {
+                *transform = *held;
+            }
+        }
+    }
+}
+
+// Puts the live transforms back, so that animations and
+// systems continue from where they were rather than from the
+// held pose
+fn restore_live_transforms(
+    mut commands: Commands,
+    mut stepped: Query<(
+        Entity,
+        &mut SteppedPoses,
+        Has<SteppedAnimation>,
+    )>,
+    mut transforms: Query<&mut Transform>,
+) {
+    for (entity, mut poses, stepping) in &mut stepped {
+        for (entity, live) in poses.live.drain(..) {
+            if let Ok(mut transform) =
+                transforms.get_mut(entity)
+            {
+                *transform = live;
+            }
+        }
+        if !stepping {
+            commands
+                .entity(entity)
+                .remove::<SteppedPoses>();
+        }
+    }
+}
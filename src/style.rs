@@ -0,0 +1,408 @@
+//! [`PixelStyle`] assets describe the look of pixelated
+//! materials in a `.pixelstyle.ron` file, so that it can be
+//! tuned without recompiling. With Bevy's `file_watcher`
+//! feature, saving the file updates every material using it.
+//!
+//! A material uses a style through
+//! [`PixelatedExtension::style`]. Materials without a style
+//! use the style on the [`PixelatedCamera`], if it has one:
+//!
+//! ```rust,ignore
+//! commands.spawn((
+//!     Camera3dBundle::default(),
+//!     PixelatedCamera::default(),
+//!     asset_server.load::<PixelStyle>("styles/default.pixelstyle.ron"),
+//! ));
+//! ```
+use crate::{
+    pixelating_plugin::PixelatedCamera, PixelPalette,
+    PixelatedExtension,
+};
+use bevy::{
+    asset::{
+        io::Reader, AssetLoader, AsyncReadExt, LoadContext,
+    },
+    pbr::ExtendedMaterial,
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+use std::fmt;
+
+pub struct PixelStylePlugin;
+
+impl Plugin for PixelStylePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<PixelStyle>()
+            .init_asset_loader::<PixelStyleLoader>()
+            .add_systems(Update, apply_pixel_styles);
+    }
+}
+
+/// The look of pixelated materials
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PixelStyle {
+    /// number of OKLab lightness bands
+    pub quantize_steps: u32,
+    pub outline: OutlineStyle,
+    pub texture: TextureStyle,
+    pub highlights: HighlightStyle,
+    pub hue_shift: HueShiftStyle,
+    /// Colors the quantized color is snapped to. Empty turns
+    /// snapping off, at most
+    /// [`MAX_PALETTE_COLORS`](crate::MAX_PALETTE_COLORS) are
+    /// used.
+    pub palette: Vec<Color>,
+    /// 0 turns dithering between lightness bands off, 1
+    /// dithers across a whole band
+    pub dither: f32,
+    pub debug_view: PixelDebugView,
+}
+
+impl Default for PixelStyle {
+    fn default() -> Self {
+        let extension = PixelatedExtension::default();
+        Self {
+            quantize_steps: extension.quantize_steps,
+            outline: OutlineStyle::default(),
+            texture: TextureStyle::default(),
+            highlights: HighlightStyle::default(),
+            hue_shift: HueShiftStyle::default(),
+            palette: vec![],
+            dither: extension.dither,
+            debug_view: PixelDebugView::None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OutlineStyle {
+    /// how much the depth outline darkens the color
+    pub strength: f32,
+    /// how much the normal edges brighten the color
+    pub normal_edge_strength: f32,
+    /// depth difference, in view space, that counts as an
+    /// outline
+    pub depth_tolerance: f32,
+    /// width of the depth outline, in low-res texels
+    pub width: u32,
+    /// with a perspective camera, the view distance beyond
+    /// which the outline gets thinner
+    pub falloff: f32,
+}
+
+impl Default for OutlineStyle {
+    fn default() -> Self {
+        let extension = PixelatedExtension::default();
+        Self {
+            strength: extension.outline_strength,
+            normal_edge_strength: extension
+                .normal_edge_strength,
+            depth_tolerance: extension.depth_tolerance,
+            width: extension.outline_width,
+            falloff: extension.outline_falloff,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TextureStyle {
+    pub pixelation: TexturePixelation,
+    /// texels shown per low-res pixel
+    pub texel_density: f32,
+    /// added to the mip level, positive values blur
+    pub mip_bias: f32,
+}
+
+impl Default for TextureStyle {
+    fn default() -> Self {
+        let extension = PixelatedExtension::default();
+        Self {
+            pixelation: TexturePixelation::Off,
+            texel_density: extension.texel_density,
+            mip_bias: extension.texture_mip_bias,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HighlightStyle {
+    /// specular strength drawn as a highlight, 0 for none
+    pub specular_threshold: f32,
+    pub specular_color: Color,
+    /// share of grazing angles in the rim light, 0 for none
+    pub rim_width: f32,
+    pub rim_color: Color,
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        let extension = PixelatedExtension::default();
+        Self {
+            specular_threshold: extension
+                .specular_threshold,
+            specular_color: extension.specular_color,
+            rim_width: extension.rim_width,
+            rim_color: extension.rim_color,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HueShiftStyle {
+    /// degrees the hue turns per band, 0 for none
+    pub degrees: f32,
+    /// chroma change per shadow band
+    pub chroma: f32,
+    /// OKLCh hue of the shadows, in degrees
+    pub shadow_hue: f32,
+    /// OKLCh hue of the highlights, in degrees
+    pub highlight_hue: f32,
+}
+
+impl Default for HueShiftStyle {
+    fn default() -> Self {
+        let extension = PixelatedExtension::default();
+        Self {
+            degrees: extension.hue_shift,
+            chroma: extension.chroma_shift,
+            shadow_hue: extension.shadow_hue,
+            highlight_hue: extension.highlight_hue,
+        }
+    }
+}
+
+/// How the base color texture of a pixelated material is
+/// sampled. High-res textures alias in the low-res image
+/// unless they are filtered down to the size of a low-res
+/// pixel.
+#[derive(
+    Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+pub enum TexturePixelation {
+    /// sampled like any `StandardMaterial` texture
+    #[default]
+    Off,
+    /// averaged over the texels a low-res pixel covers, which
+    /// also works for textures without mips
+    Filtered,
+    /// snapped to a grid of texture space cells, each the
+    /// size of a low-res pixel, so that the texture reads as
+    /// pixel art that stays put when the camera moves
+    Quantized,
+}
+
+/// Replaces the shaded color to debug the pixelated effect
+#[derive(
+    Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq,
+)]
+pub enum PixelDebugView {
+    #[default]
+    None,
+    /// the depth prepass
+    Depth,
+    /// the normal prepass
+    Normals,
+    /// the depth outline
+    Outline,
+    /// the edges found in the normal prepass
+    NormalEdges,
+}
+
+impl PixelDebugView {
+    /// The view after this one, wrapping back to
+    /// [`PixelDebugView::None`]
+    pub fn next(self) -> Self {
+        match self {
+            PixelDebugView::None => PixelDebugView::Depth,
+            PixelDebugView::Depth => {
+                PixelDebugView::Normals
+            }
+            PixelDebugView::Normals => {
+                PixelDebugView::Outline
+            }
+            PixelDebugView::Outline => {
+                PixelDebugView::NormalEdges
+            }
+            PixelDebugView::NormalEdges => {
+                PixelDebugView::None
+            }
+        }
+    }
+}
+
+impl PixelStyle {
+    /// Copies the style into a material's settings
+    pub fn apply(
+        &self,
+        extension: &mut PixelatedExtension,
+    ) {
+        extension.quantize_steps = self.quantize_steps;
+        extension.outline_strength = self.outline.strength;
+        extension.normal_edge_strength =
+            self.outline.normal_edge_strength;
+        extension.depth_tolerance =
+            self.outline.depth_tolerance;
+        extension.outline_width = self.outline.width;
+        extension.outline_falloff = self.outline.falloff;
+        extension.texture_pixelation =
+            self.texture.pixelation as u32;
+        extension.texel_density =
+            self.texture.texel_density;
+        extension.texture_mip_bias = self.texture.mip_bias;
+        extension.specular_threshold =
+            self.highlights.specular_threshold;
+        extension.specular_color =
+            self.highlights.specular_color;
+        extension.rim_width = self.highlights.rim_width;
+        extension.rim_color = self.highlights.rim_color;
+        extension.dither = self.dither;
+        extension.hue_shift = self.hue_shift.degrees;
+        extension.chroma_shift = self.hue_shift.chroma;
+        extension.shadow_hue = self.hue_shift.shadow_hue;
+        extension.highlight_hue =
+            self.hue_shift.highlight_hue;
+        extension.debug_view = self.debug_view as u32;
+        extension.palette =
+            PixelPalette::new(&self.palette);
+    }
+}
+
+#[derive(Default)]
+pub struct PixelStyleLoader;
+
+#[derive(Debug)]
+pub enum PixelStyleLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for PixelStyleLoaderError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            PixelStyleLoaderError::Io(error) => {
+                write!(
+                    f,
+                    "cannot read pixel style: {error}"
+                )
+            }
+            PixelStyleLoaderError::Ron(error) => {
+                write!(
+                    f,
+                    "cannot parse pixel style: {error}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PixelStyleLoaderError {}
+
+impl AssetLoader for PixelStyleLoader {
+    type Asset = PixelStyle;
+    type Settings = ();
+    type Error = PixelStyleLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<PixelStyle, Self::Error>>
+    {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(PixelStyleLoaderError::Io)?;
+            ron::de::from_bytes(&bytes)
+                .map_err(PixelStyleLoaderError::Ron)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pixelstyle.ron"]
+    }
+}
+
+type PixelatedMaterial =
+    ExtendedMaterial<StandardMaterial, PixelatedExtension>;
+
+// Copies styles into the materials using them whenever a
+// style, a material or the camera's style changes
+fn apply_pixel_styles(
+    mut style_events: EventReader<AssetEvent<PixelStyle>>,
+    mut material_events: EventReader<
+        AssetEvent<PixelatedMaterial>,
+    >,
+    styles: Res<Assets<PixelStyle>>,
+    mut materials: ResMut<Assets<PixelatedMaterial>>,
+    camera_styles: Query<
+        Ref<Handle<PixelStyle>>,
+        With<PixelatedCamera>,
+    >,
+) {
+    let camera_style = camera_styles.get_single().ok();
+    // only react to new materials, as applying a style
+    // modifies the material itself
+    let new_materials: Vec<AssetId<PixelatedMaterial>> =
+        material_events
+            .read()
+            .filter_map(|event| match event {
+                AssetEvent::Added { id } => Some(*id),
+                _ => None,
+            })
+            .collect();
+    let styles_changed = style_events
+        .read()
+        .filter(|event| {
+            matches!(
+                event,
+                AssetEvent::LoadedWithDependencies { .. }
+                    | AssetEvent::Modified { .. }
+            )
+        })
+        .count()
+        > 0
+        || camera_style
+            .as_ref()
+            .is_some_and(|style| style.is_changed());
+
+    if !styles_changed && new_materials.is_empty() {
+        return;
+    }
+    let ids: Vec<AssetId<PixelatedMaterial>> =
+        if styles_changed {
+            materials.ids().collect()
+        } else {
+            new_materials
+        };
+    for id in ids {
+        // read first, so that materials without a style
+        // aren't marked as modified
+        let Some(style) =
+            materials.get(id).and_then(|material| {
+                material
+                    .extension
+                    .style
+                    .as_ref()
+                    .or(camera_style.as_deref())
+                    .and_then(|handle| styles.get(handle))
+            })
+        else {
+            continue;
+        };
+        if let Some(material) = materials.get_mut(id) {
+            style.apply(&mut material.extension);
+        }
+    }
+}